@@ -1,4 +1,4 @@
-#[derive(Debug, FromPrimitive, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, FromPrimitive, PartialEq, Eq)]
 pub enum Bytecode {
     Cache = 0,
     PopTop = 1,
@@ -84,6 +84,7 @@ pub enum Bytecode {
     PopJumpIfNone = 129,
     RaiseVarargs = 130,
     CallFunction = 131,
+    MakeFunction = 132,
     BuildSlice = 133,
     JumpBackwardNoInterrupt = 134,
     MakeCell = 135,
@@ -102,6 +103,7 @@ pub enum Bytecode {
     YieldValue = 150,
     Resume = 151,
     MatchClass = 152,
+    FormatValue = 155,
     BuildConstKeyMap = 156,
     BuildString = 157,
     ConvertValue = 158,
@@ -150,4 +152,134 @@ pub enum Bytecode {
     LoadZeroSuperAttr = 265,
     StoreFastMaybeNull = 266,
     LoadClosure = 267,
+    // Instructions of CPython 3.10 without an equivalent above
+    RotN = 268,
+    DupTopTwo = 269,
+    JumpIfNotExcMatch = 270,
+    YieldFrom = 271,
+    GetAwaitable = 272,
+    LoadClassDeref = 273,
+    CopyDictWithoutKeys = 274,
+    ImportStar = 275,
+    PrintExpr = 276,
+    JumpIfFalseOrPop = 277,
+    JumpIfTrueOrPop = 278,
+}
+
+// Arguments of `Bytecode::BinaryOp`, following `NB_*` in CPython's `opcode.h`.
+pub const NB_ADD: u32 = 0;
+pub const NB_AND: u32 = 1;
+pub const NB_FLOOR_DIVIDE: u32 = 2;
+pub const NB_LSHIFT: u32 = 3;
+pub const NB_MATRIX_MULTIPLY: u32 = 4;
+pub const NB_MULTIPLY: u32 = 5;
+pub const NB_REMAINDER: u32 = 6;
+pub const NB_OR: u32 = 7;
+pub const NB_POWER: u32 = 8;
+pub const NB_RSHIFT: u32 = 9;
+pub const NB_SUBTRACT: u32 = 10;
+pub const NB_TRUE_DIVIDE: u32 = 11;
+pub const NB_XOR: u32 = 12;
+pub const NB_INPLACE_ADD: u32 = 13;
+pub const NB_INPLACE_AND: u32 = 14;
+pub const NB_INPLACE_FLOOR_DIVIDE: u32 = 15;
+pub const NB_INPLACE_LSHIFT: u32 = 16;
+pub const NB_INPLACE_MATRIX_MULTIPLY: u32 = 17;
+pub const NB_INPLACE_MULTIPLY: u32 = 18;
+pub const NB_INPLACE_REMAINDER: u32 = 19;
+pub const NB_INPLACE_OR: u32 = 20;
+pub const NB_INPLACE_POWER: u32 = 21;
+pub const NB_INPLACE_RSHIFT: u32 = 22;
+pub const NB_INPLACE_SUBTRACT: u32 = 23;
+pub const NB_INPLACE_TRUE_DIVIDE: u32 = 24;
+pub const NB_INPLACE_XOR: u32 = 25;
+
+// Arguments of `Bytecode::CallIntrinsic1`, following `INTRINSIC_*` in CPython's
+// `pycore_intrinsics.h`.
+pub const INTRINSIC_PRINT: u32 = 1;
+pub const INTRINSIC_IMPORT_STAR: u32 = 2;
+pub const INTRINSIC_UNARY_POSITIVE: u32 = 5;
+pub const INTRINSIC_LIST_TO_TUPLE: u32 = 6;
+
+/// Decodes an instruction of the running interpreter (CPython 3.10). Opcodes which 3.10 numbers
+/// differently from `Bytecode` are translated to their equivalent, e.g. `BINARY_MULTIPLY` becomes
+/// `BinaryOp` with `NB_MULTIPLY`.
+pub fn decode(opcode: u8, arg: u32) -> Option<(Bytecode, u32)> {
+    let binary_op = match opcode {
+        16 => Some(NB_MATRIX_MULTIPLY),
+        17 => Some(NB_INPLACE_MATRIX_MULTIPLY),
+        19 => Some(NB_POWER),
+        20 => Some(NB_MULTIPLY),
+        22 => Some(NB_REMAINDER),
+        26 => Some(NB_FLOOR_DIVIDE),
+        27 => Some(NB_TRUE_DIVIDE),
+        28 => Some(NB_INPLACE_FLOOR_DIVIDE),
+        29 => Some(NB_INPLACE_TRUE_DIVIDE),
+        55 => Some(NB_INPLACE_ADD),
+        56 => Some(NB_INPLACE_SUBTRACT),
+        57 => Some(NB_INPLACE_MULTIPLY),
+        59 => Some(NB_INPLACE_REMAINDER),
+        62 => Some(NB_LSHIFT),
+        63 => Some(NB_RSHIFT),
+        64 => Some(NB_AND),
+        65 => Some(NB_XOR),
+        66 => Some(NB_OR),
+        67 => Some(NB_INPLACE_POWER),
+        75 => Some(NB_INPLACE_LSHIFT),
+        76 => Some(NB_INPLACE_RSHIFT),
+        77 => Some(NB_INPLACE_AND),
+        78 => Some(NB_INPLACE_XOR),
+        79 => Some(NB_INPLACE_OR),
+        _ => None,
+    };
+    match (binary_op, opcode) {
+        (Some(op), _) => Some((Bytecode::BinaryOp, op)),
+        // GEN_START, which pops the value sent to start a generator
+        (None, 129) => Some((Bytecode::PopTop, 0)),
+        // YIELD_VALUE, YIELD_FROM, GET_AWAITABLE and JUMP_ABSOLUTE
+        (None, 86) => Some((Bytecode::YieldValue, arg)),
+        (None, 72) => Some((Bytecode::YieldFrom, arg)),
+        (None, 73) => Some((Bytecode::GetAwaitable, arg)),
+        (None, 113) => Some((Bytecode::Jump, arg)),
+        // LOAD_CLOSURE, LOAD_DEREF, STORE_DEREF, DELETE_DEREF and LOAD_CLASSDEREF, which index
+        // the cell and free variables
+        (None, 135) => Some((Bytecode::LoadClosure, arg)),
+        (None, 136) => Some((Bytecode::LoadDeref, arg)),
+        (None, 137) => Some((Bytecode::StoreDeref, arg)),
+        (None, 138) => Some((Bytecode::DeleteDeref, arg)),
+        (None, 148) => Some((Bytecode::LoadClassDeref, arg)),
+        // UNARY_POSITIVE and LIST_TO_TUPLE, which 3.12 calls as intrinsics, and IMPORT_STAR and
+        // PRINT_EXPR, which push nothing unlike the intrinsics
+        (None, 10) => Some((Bytecode::CallIntrinsic1, INTRINSIC_UNARY_POSITIVE)),
+        (None, 82) => Some((Bytecode::CallIntrinsic1, INTRINSIC_LIST_TO_TUPLE)),
+        (None, 84) => Some((Bytecode::ImportStar, arg)),
+        (None, 70) => Some((Bytecode::PrintExpr, arg)),
+        // COPY_DICT_WITHOUT_KEYS, for the rest of a mapping pattern
+        (None, 34) => Some((Bytecode::CopyDictWithoutKeys, arg)),
+        // ROT_TWO, ROT_THREE, DUP_TOP, DUP_TOP_TWO, ROT_FOUR and ROT_N
+        (None, 2) => Some((Bytecode::Swap, 2)),
+        (None, 3) => Some((Bytecode::RotN, 3)),
+        (None, 4) => Some((Bytecode::Copy, 1)),
+        (None, 5) => Some((Bytecode::DupTopTwo, 0)),
+        (None, 6) => Some((Bytecode::RotN, 4)),
+        (None, 99) => Some((Bytecode::RotN, arg)),
+        // SETUP_FINALLY, POP_BLOCK and JUMP_IF_NOT_EXC_MATCH
+        (None, 122) => Some((Bytecode::SetupFinally, arg)),
+        (None, 87) => Some((Bytecode::PopBlock, arg)),
+        (None, 121) => Some((Bytecode::JumpIfNotExcMatch, arg)),
+        // JUMP_IF_FALSE_OR_POP and JUMP_IF_TRUE_OR_POP, which keep the value when jumping
+        (None, 111) => Some((Bytecode::JumpIfFalseOrPop, arg)),
+        (None, 112) => Some((Bytecode::JumpIfTrueOrPop, arg)),
+        // SETUP_WITH, and WITH_EXCEPT_START, which finds `__exit__` below the six values pushed
+        // for a handler in 3.10
+        (None, 143) => Some((Bytecode::SetupWith, arg)),
+        (None, 49) => Some((Bytecode::WithExceptStart, arg)),
+        // Opcodes which `Bytecode` numbers as another instruction, e.g. CALL_FUNCTION_KW is
+        // LoadSuperAttr here
+        (None, 50 | 51 | 52 | 141) => None,
+        (None, 160) => Some((Bytecode::LoadMethod, arg)),
+        // CALL_METHOD, whose operands are laid out like those of CALL
+        (None, 161) => Some((Bytecode::Call, arg)),
+        (None, _) => num::FromPrimitive::from_u8(opcode).map(|code| (code, arg)),
+    }
 }
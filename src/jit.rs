@@ -1,37 +1,91 @@
 use log::{debug, info};
+use pyo3::ffi::structmember::{PyMemberDef, READONLY, T_OBJECT_EX};
 use pyo3::ffi::{
-    PyBool_FromLong, PyBytes_AsString, PyBytes_Check, PyBytes_Size, PyDict_GetItem, PyDict_New,
-    PyFrameObject, PyFrame_Check, PyInterpreterState_Get, PyLong_AsLong, PyLong_FromLong, PyObject,
-    PyObject_Call, PyThreadState, PyThreadState_Get, PyTuple_GetItem, PyTuple_New, PyTuple_SetItem,
-    Py_IsTrue,
+    _PyCode_GetExtra, _PyCode_SetExtra, _PyDict_NewPresized, _PyEval_RequestCodeExtraIndex,
+    _PyObject_GetDictPtr, _Py_Dealloc, getattrofunc, setattrofunc, PyBaseObject_Type, PyBool_Type,
+    PyBytes_AsString, PyBytes_Check, PyBytes_Size, PyCFunctionObject, PyCFunction_Check,
+    PyCodeObject, PyCoro_CheckExact, PyDictObject, PyDict_Contains, PyDict_DelItem, PyDict_GetItem,
+    PyDict_GetItemWithError, PyDict_New, PyDict_SetItem, PyDict_Type, PyDict_Update, PyErr_Clear,
+    PyErr_ExceptionMatches, PyErr_Fetch, PyErr_Format, PyErr_GetExcInfo,
+    PyErr_GivenExceptionMatches, PyErr_NormalizeException, PyErr_Occurred, PyErr_Restore,
+    PyErr_SetExcInfo, PyErr_SetImportError, PyErr_SetObject, PyErr_SetString, PyExc_AttributeError,
+    PyExc_ImportError, PyExc_IndexError, PyExc_KeyError, PyExc_NameError, PyExc_RuntimeError,
+    PyExc_StopIteration, PyExc_SystemError, PyExc_TypeError, PyExc_UnboundLocalError,
+    PyExc_ValueError, PyExceptionClass_Check, PyExceptionInstance_Check, PyException_SetCause,
+    PyException_SetTraceback, PyFloat_AsDouble, PyFloat_FromDouble, PyFloat_Type, PyFrameObject,
+    PyFrame_BlockSetup, PyFrame_Check, PyFrame_FastToLocalsWithError, PyFrame_LocalsToFast,
+    PyFrozenSet_Type, PyFunctionObject, PyFunction_NewWithQualName, PyGenObject, PyGen_CheckExact,
+    PyImport_ImportModuleLevelObject, PyInterpreterState_Get, PyIter_Check, PyIter_Next,
+    PyList_Append, PyList_AsTuple, PyList_GetItem, PyList_New, PyList_SET_ITEM, PyList_Size,
+    PyList_Type, PyLong_AsLong, PyLong_AsLongAndOverflow, PyLong_CheckExact, PyLong_FromLong,
+    PyLong_FromSsize_t, PyLong_Type, PyMapping_Keys, PyMemberDescrObject, PyMemberDescr_Type,
+    PyModule_Check, PyModule_GetFilenameObject, PyModule_GetName, PyNumber_Add, PyNumber_And,
+    PyNumber_FloorDivide, PyNumber_InPlaceAdd, PyNumber_InPlaceAnd, PyNumber_InPlaceFloorDivide,
+    PyNumber_InPlaceLshift, PyNumber_InPlaceMatrixMultiply, PyNumber_InPlaceMultiply,
+    PyNumber_InPlaceOr, PyNumber_InPlacePower, PyNumber_InPlaceRemainder, PyNumber_InPlaceRshift,
+    PyNumber_InPlaceSubtract, PyNumber_InPlaceTrueDivide, PyNumber_InPlaceXor, PyNumber_Invert,
+    PyNumber_Lshift, PyNumber_MatrixMultiply, PyNumber_Multiply, PyNumber_Negative, PyNumber_Or,
+    PyNumber_Positive, PyNumber_Power, PyNumber_Remainder, PyNumber_Rshift, PyNumber_Subtract,
+    PyNumber_TrueDivide, PyNumber_Xor, PyObject, PyObject_ASCII, PyObject_Call,
+    PyObject_CallNoArgs, PyObject_DelItem, PyObject_Format, PyObject_GenericGetAttr,
+    PyObject_GenericSetAttr, PyObject_GetAttr, PyObject_GetAttrString, PyObject_GetItem,
+    PyObject_GetIter, PyObject_IsInstance, PyObject_IsTrue, PyObject_Length, PyObject_Repr,
+    PyObject_RichCompare, PyObject_SetAttr, PyObject_SetItem, PyObject_Str,
+    PyObject_VectorcallDict, PySequence_Check, PySequence_Contains, PySequence_GetItem,
+    PySequence_List, PySet_Add, PySet_Contains, PySet_New, PySet_Type, PySlice_New, PySuper_Type,
+    PySys_GetObject, PyThreadState, PyThreadState_Get, PyTraceBack_Here, PyTuple_Check,
+    PyTuple_CheckExact, PyTuple_GetItem, PyTuple_New, PyTuple_SET_ITEM, PyTuple_Size, PyTuple_Type,
+    PyTypeObject, PyType_Check, PyType_IsSubtype, PyUnicode_Check, PyUnicode_CheckExact,
+    PyUnicode_CompareWithASCIIString, PyUnicode_FromFormat, PyUnicode_FromString,
+    PyUnicode_GetLength, PyUnicode_InternFromString, PyUnicode_New, PyUnicode_ReadChar,
+    PyUnicode_Type, PyVarObject, Py_DECREF, Py_EQ, Py_False, Py_GE, Py_GT, Py_INCREF, Py_LE, Py_LT,
+    Py_None, Py_TPFLAGS_MAPPING, Py_TPFLAGS_METHOD_DESCRIPTOR, Py_TPFLAGS_SEQUENCE,
+    Py_TPFLAGS_VALID_VERSION_TAG, Py_TYPE, Py_True, Py_XDECREF, Py_XINCREF, Py_ssize_t,
+    CO_ASYNC_GENERATOR, CO_COROUTINE, CO_GENERATOR, CO_ITERABLE_COROUTINE,
 };
-use std::io::{self, Write};
 
 #[path = "bytecode.rs"]
 mod bytecode;
-use bytecode::Bytecode;
+use bytecode::*;
 #[path = "pyutils.rs"]
 mod pyutils;
-use pyutils::{dump_frame_info, get_type};
+use pyutils::get_type;
 
 extern crate libc;
 use iced_x86::{Decoder, DecoderOptions, Formatter, Instruction, IntelFormatter};
-use libc::{c_int, c_void, size_t, PROT_EXEC, PROT_READ, PROT_WRITE};
+use libc::{c_int, c_uint, c_ulong, c_void, size_t, PROT_EXEC, PROT_NONE, PROT_READ, PROT_WRITE};
 use std::alloc::{alloc, Layout};
+use std::collections::HashMap;
+use std::ffi::CStr;
+use std::sync::atomic::{AtomicPtr, Ordering};
 
 extern "C" {
     fn mprotect(addr: *const c_void, len: size_t, prot: c_int) -> c_int;
+    fn _PyType_Lookup(ty: *mut PyTypeObject, name: *mut PyObject) -> *mut PyObject;
+    fn _PyList_Extend(list: *mut PyObject, iterable: *mut PyObject) -> *mut PyObject;
+    fn _PySet_Update(set: *mut PyObject, iterable: *mut PyObject) -> c_int;
+    fn _PyDict_MergeEx(a: *mut PyObject, b: *mut PyObject, override_: c_int) -> c_int;
+    fn _PyObject_FunctionStr(func: *mut PyObject) -> *mut PyObject;
+    fn _PyErr_SetKeyError(key: *mut PyObject);
+    fn _PyObject_LookupAttr(
+        v: *mut PyObject,
+        name: *mut PyObject,
+        result: *mut *mut PyObject,
+    ) -> c_int;
+    fn _PyLong_AsInt(v: *mut PyObject) -> c_int;
+    fn _PyModuleSpec_IsInitializing(spec: *mut PyObject) -> c_int;
+    fn PyImport_GetModule(name: *mut PyObject) -> *mut PyObject;
+    fn _PyUnicode_JoinArray(
+        separator: *mut PyObject,
+        items: *const *mut PyObject,
+        n: isize,
+    ) -> *mut PyObject;
+    static mut PyCell_Type: PyTypeObject;
+    // pyo3 declares it without the `PySendResult` it returns.
+    fn PyIter_Send(iter: *mut PyObject, arg: *mut PyObject, presult: *mut *mut PyObject) -> c_int;
 }
 
-macro_rules! jit_log {
-    ($x:expr) => {
-        let _ = io::stdout().write_all(
-            (file!().to_owned() + ":" + &line!().to_string() + " jit_log " + $x + "\n").as_bytes(),
-        );
-        let _ = io::stdout().flush();
-    };
-}
-
+#[allow(dead_code)]
 fn write_nop(buf: *mut u8, index: usize) -> usize {
     unsafe { *(buf.add(index)) = 0x90 };
     index + 1
@@ -52,40 +106,60 @@ fn write_mov_rax(buf: *mut u8, index: usize, value: u64) -> usize {
     index + 10
 }
 
-fn write_call_rax(buf: *mut u8, index: usize) -> usize {
-    // let i = write_push_r11(buf, index);
-    // unsafe { *(buf.add(i + 0)) = 0xcc };
-    // unsafe { *(buf.add(i + 1)) = 0xff };
-    // unsafe { *(buf.add(i + 2)) = 0xd0 };
-    // write_pop_r11(buf, i + 3)
-
-    // https://stackoverflow.com/questions/9592345/x86-64-align-stack-and-recover-without-saving-registers
-    // push   rsp
-    // 54
-    unsafe { *(buf.add(index + 0)) = 0x54 };
-    // push   QWORD PTR [rsp]
-    // ff 34 24
-    unsafe { *(buf.add(index + 1)) = 0xff };
-    unsafe { *(buf.add(index + 2)) = 0x34 };
-    unsafe { *(buf.add(index + 3)) = 0x24 };
-    // and    rsp,0xfffffffffffffff0
-    // 48 83 e4 f0
-    unsafe { *(buf.add(index + 4)) = 0x48 };
-    unsafe { *(buf.add(index + 5)) = 0x83 };
-    unsafe { *(buf.add(index + 6)) = 0xe4 };
-    unsafe { *(buf.add(index + 7)) = 0xf0 };
-    // call   rax
-    // ff d0
-    unsafe { *(buf.add(index + 8)) = 0xff };
-    unsafe { *(buf.add(index + 9)) = 0xd0 };
-    // mov    rsp,QWORD PTR [rsp+0x8]
-    // 48 8b 64 24 08
-    unsafe { *(buf.add(index + 10)) = 0x48 };
-    unsafe { *(buf.add(index + 11)) = 0x8b };
-    unsafe { *(buf.add(index + 12)) = 0x64 };
-    unsafe { *(buf.add(index + 13)) = 0x24 };
-    unsafe { *(buf.add(index + 14)) = 0x08 };
-    index + 15
+// Calls RAX with `depth` values on the native stack, i.e. RSP = RBP-8*depth. RBP is 16-byte
+// aligned, so RSP is aligned at the call for an even depth and an odd one is padded by a slot.
+//   sub rsp, 8 (odd depth)
+//   call rax
+//   add rsp, 8 (odd depth)
+fn write_call_rax(buf: *mut u8, index: usize, depth: usize) -> usize {
+    if depth.is_multiple_of(2) {
+        write_bytes(buf, index, &[0xff, 0xd0])
+    } else {
+        write_bytes(
+            buf,
+            index,
+            &[0x48, 0x83, 0xec, 0x08, 0xff, 0xd0, 0x48, 0x83, 0xc4, 0x08],
+        )
+    }
+}
+
+// Enters compiled code, which is called as `JitFunction` with the thread state in RDI and the
+// frame in RSI. RBX, R12 and RBP are saved so that RBP is 16-byte aligned, the thread state is
+// kept in R12 and the frame becomes the thread's current one.
+//   endbr64
+//   push rbx
+//   push r12
+//   push rbp
+//   mov rbp, rsp
+//   mov r12, rdi
+//   mov [rdi+frame], rsi
+fn write_prologue(buf: *mut u8, index: usize) -> usize {
+    let index = write_endbr64(buf, index);
+    let index = write_bytes(buf, index, &[0x53, 0x41, 0x54]);
+    let index = write_push_rbp(buf, index);
+    let index = write_mov_rsp_to_rbp(buf, index);
+    let frame = std::mem::offset_of!(ThreadState, frame) as u8;
+    write_bytes(buf, index, &[0x49, 0x89, 0xfc, 0x48, 0x89, 0x77, frame])
+}
+
+// Leaves compiled code with the result in RAX, making the caller's frame the thread's current
+// one again and restoring the registers saved by `write_prologue`.
+//   mov rcx, frame
+//   mov rcx, [rcx+f_back]
+//   mov [r12+frame], rcx
+//   leave
+//   pop r12
+//   pop rbx
+//   ret
+fn write_epilogue(buf: *mut u8, index: usize, frame: *mut PyFrameObject) -> usize {
+    let index = write_mov_rcx(buf, index, frame as u64);
+    let f_back = std::mem::offset_of!(PyFrameObject, f_back) as u8;
+    let thread_frame = std::mem::offset_of!(ThreadState, frame) as u8;
+    let index = write_bytes(buf, index, &[0x48, 0x8b, 0x49, f_back]);
+    let index = write_bytes(buf, index, &[0x49, 0x89, 0x4c, 0x24, thread_frame]);
+    let index = write_leave(buf, index);
+    let index = write_bytes(buf, index, &[0x41, 0x5c, 0x5b]);
+    write_ret(buf, index)
 }
 
 fn write_leave(buf: *mut u8, index: usize) -> usize {
@@ -128,7 +202,6 @@ fn write_push_rbp(buf: *mut u8, index: usize) -> usize {
     index + 1
 }
 
-#[allow(dead_code)]
 fn write_mov_rsp_to_rbp(buf: *mut u8, index: usize) -> usize {
     unsafe { *(buf.add(index)) = 0x48 };
     unsafe { *(buf.add(index + 1)) = 0x89 };
@@ -185,370 +258,5978 @@ fn write_pop_rsi(buf: *mut u8, index: usize) -> usize {
     index + 1
 }
 
-fn add_py_longs(a: *mut PyObject, b: *mut PyObject) -> *mut PyObject {
-    unsafe {
-        let a = PyLong_AsLong(a);
-        let b = PyLong_AsLong(b);
-        let c = a + b;
-        PyLong_FromLong(c)
-    }
+fn write_pop_rdx(buf: *mut u8, index: usize) -> usize {
+    unsafe { *(buf.add(index)) = 0x5a };
+    index + 1
 }
 
-fn sub_py_longs(a: *mut PyObject, b: *mut PyObject) -> *mut PyObject {
-    unsafe {
-        let a = PyLong_AsLong(a);
-        let b = PyLong_AsLong(b);
-        let c = a - b;
-        jit_log!(&format!("sub_py_longs a:{:?} b:{:?} c:{:?}", a, b, c));
-        PyLong_FromLong(c)
+fn write_bytes(buf: *mut u8, index: usize, bytes: &[u8]) -> usize {
+    for (i, b) in bytes.iter().enumerate() {
+        unsafe { *(buf.add(index + i)) = *b };
     }
+    index + bytes.len()
 }
 
-fn compare_py_longs(a: *mut PyObject, b: *mut PyObject) -> *mut PyObject {
-    unsafe {
-        let a = PyLong_AsLong(a);
-        let b = PyLong_AsLong(b);
-        PyBool_FromLong(if b - a < 0 { 1 } else { 0 })
-    }
+fn write_rel32(buf: *mut u8, index: usize, offset: i32) -> usize {
+    write_bytes(buf, index, &offset.to_le_bytes())
 }
 
-fn check_py_bool(a: *mut PyObject) -> i64 {
-    let b = unsafe { Py_IsTrue(a) };
-    jit_log!(&format!("check_py_bool:{}", b));
-    if b == 1 {
-        1
-    } else {
-        0
-    }
+fn write_mov_rcx(buf: *mut u8, index: usize, value: u64) -> usize {
+    let index = write_bytes(buf, index, &[0x48, 0xb9]);
+    write_bytes(buf, index, &value.to_le_bytes())
 }
 
-pub static mut ORIGINAL_FRAME: Option<
-    extern "C" fn(state: *mut PyThreadState, frame: *mut PyFrameObject, c: i32) -> *mut PyObject,
-> = None;
+fn write_mov_edx(buf: *mut u8, index: usize, value: u32) -> usize {
+    let index = write_bytes(buf, index, &[0xba]);
+    write_bytes(buf, index, &value.to_le_bytes())
+}
 
-pub extern "C" fn eval(
-    state: *mut PyThreadState,
-    frame: *mut PyFrameObject,
-    c: i32,
-) -> *mut PyObject {
-    info!("eval()");
+fn write_pop_rcx(buf: *mut u8, index: usize) -> usize {
+    write_bytes(buf, index, &[0x59])
+}
 
-    let jit_result = compile_and_exec_jit_code(state, frame, c);
+// add qword ptr [rax], 1
+fn write_incref_rax(buf: *mut u8, index: usize) -> usize {
+    write_bytes(buf, index, &[0x48, 0x83, 0x00, 0x01])
+}
 
-    match jit_result {
-        Some(result) => {
-            info!("jit result: {:?}", result);
-            result
-        }
-        None => unsafe {
-            if let Some(original) = ORIGINAL_FRAME {
-                original(state, frame, c)
-            } else {
-                panic!("original frame not found");
-            }
-        },
-    }
+// sub qword ptr [rax], 1
+// Only for objects whose refcount is known not to reach 0.
+fn write_decref_rax_inline(buf: *mut u8, index: usize) -> usize {
+    write_bytes(buf, index, &[0x48, 0x83, 0x28, 0x01])
+}
+
+// mov rax, qword ptr [rsp]
+fn write_load_rax_top(buf: *mut u8, index: usize) -> usize {
+    write_bytes(buf, index, &[0x48, 0x8b, 0x04, 0x24])
+}
+
+// mov qword ptr [rsp], rax
+fn write_store_rax_top(buf: *mut u8, index: usize) -> usize {
+    write_bytes(buf, index, &[0x48, 0x89, 0x04, 0x24])
+}
+
+// mov rdx, qword ptr [rsp+8]
+fn write_load_rdx_second(buf: *mut u8, index: usize) -> usize {
+    write_bytes(buf, index, &[0x48, 0x8b, 0x54, 0x24, 0x08])
+}
+
+const RAX: u8 = 0;
+const RCX: u8 = 1;
+
+// mov reg, qword ptr [rsp+8*slot]
+fn write_load_stack(buf: *mut u8, index: usize, reg: u8, slot: u32) -> usize {
+    let index = write_bytes(buf, index, &[0x48, 0x8b, 0x84 | reg << 3, 0x24]);
+    write_bytes(buf, index, &(8 * slot).to_le_bytes())
+}
+
+// mov qword ptr [rsp+8*slot], reg
+fn write_store_stack(buf: *mut u8, index: usize, reg: u8, slot: u32) -> usize {
+    let index = write_bytes(buf, index, &[0x48, 0x89, 0x84 | reg << 3, 0x24]);
+    write_bytes(buf, index, &(8 * slot).to_le_bytes())
+}
+
+// mov rdi, qword ptr [rsp+8*slot]
+fn write_load_rdi_stack(buf: *mut u8, index: usize, slot: u8) -> usize {
+    write_bytes(buf, index, &[0x48, 0x8b, 0x7c, 0x24, 8 * slot])
+}
+
+// cmp qword ptr [rdx+8], rcx
+fn write_cmp_ob_type_rdx_rcx(buf: *mut u8, index: usize) -> usize {
+    write_bytes(buf, index, &[0x48, 0x39, 0x4a, 0x08])
 }
 
-fn call_callable(frame: *mut PyFrameObject, arg: *mut PyObject) -> *mut PyObject {
-    info!("call_callable");
-    info!(
-        "start call_callable get_type(frame as *mut PyObject):{:?}",
-        unsafe { get_type(frame as *mut PyObject) }
+// Adds ob_size of the sequence in RDX to a negative index in RAX and jumps if the index is then
+// out of range.
+//   test rax, rax
+//   jns  +4
+//   add  rax, qword ptr [rdx+16]
+//   cmp  rax, qword ptr [rdx+16]
+//   jae  <rel32>
+fn write_check_sequence_index(buf: *mut u8, index: usize) -> usize {
+    let index = write_bytes(
+        buf,
+        index,
+        &[
+            0x48, 0x85, 0xc0, 0x79, 0x04, 0x48, 0x03, 0x42, 0x10, 0x48, 0x3b, 0x42, 0x10, 0x0f,
+            0x83,
+        ],
     );
-    info!("arg = {:?}", unsafe { PyLong_AsLong(arg) });
-
-    let state = unsafe { PyThreadState_Get() };
-    // unsafe { frame.read().f_localsplus[0] = arg };
-    info!(
-        "&(frame.read().f_localsplus[0]) = {:?} frame.read().f_localsplus[0] = {:?} arg = {:?}",
-        unsafe { &(frame.read().f_localsplus[0]) },
-        unsafe { frame.read().f_localsplus[0] },
-        arg
+    write_rel32(buf, index, 0)
+}
+
+// mov rcx, qword ptr [rdx+24]  (ob_item)
+// mov rax, qword ptr [rcx+rax*8]
+fn write_load_list_item(buf: *mut u8, index: usize) -> usize {
+    write_bytes(
+        buf,
+        index,
+        &[0x48, 0x8b, 0x4a, 0x18, 0x48, 0x8b, 0x04, 0xc1],
+    )
+}
+
+// mov rax, qword ptr [rdx+rax*8+24]
+fn write_load_tuple_item(buf: *mut u8, index: usize) -> usize {
+    write_bytes(buf, index, &[0x48, 0x8b, 0x44, 0xc2, 0x18])
+}
+
+// Decrements the reference count of the object in RDI and deallocates it when it drops to zero.
+// `depth` is the number of values on the native stack as for `write_call_rax`.
+//   sub qword ptr [rdi], 1
+//   jne <end>
+//   mov rax, dealloc
+//   call rax
+fn write_decref_rdi(buf: *mut u8, index: usize, depth: usize) -> usize {
+    let start = write_bytes(buf, index, &[0x48, 0x83, 0x2f, 0x01, 0x75, 0]);
+    let index = write_mov_rax(buf, start, dealloc as *const () as u64);
+    let index = write_call_rax(buf, index, depth);
+    unsafe { *buf.add(start - 1) = (index - start) as u8 };
+    index
+}
+
+// Like `write_decref_rdi` but does nothing for NULL.
+//   test rdi, rdi
+//   je   <end>
+fn write_xdecref_rdi(buf: *mut u8, index: usize, depth: usize) -> usize {
+    let start = write_bytes(buf, index, &[0x48, 0x85, 0xff, 0x74, 0]);
+    let index = write_decref_rdi(buf, start, depth);
+    unsafe { *buf.add(start - 1) = (index - start) as u8 };
+    index
+}
+
+// mov rax, qword ptr [rax]
+fn write_deref_rax(buf: *mut u8, index: usize) -> usize {
+    write_bytes(buf, index, &[0x48, 0x8b, 0x00])
+}
+
+// mov rdi, qword ptr [rax]
+// mov qword ptr [rax], rcx
+fn write_swap_rcx_into_deref_rax(buf: *mut u8, index: usize) -> usize {
+    write_bytes(buf, index, &[0x48, 0x8b, 0x38, 0x48, 0x89, 0x08])
+}
+
+// mov rdi, rax
+fn write_mov_rax_to_rdi(buf: *mut u8, index: usize) -> usize {
+    write_bytes(buf, index, &[0x48, 0x89, 0xc7])
+}
+
+// test rax, rax
+fn write_test_rax_rax(buf: *mut u8, index: usize) -> usize {
+    write_bytes(buf, index, &[0x48, 0x85, 0xc0])
+}
+
+// xor eax, eax
+fn write_zero_rax(buf: *mut u8, index: usize) -> usize {
+    write_bytes(buf, index, &[0x31, 0xc0])
+}
+
+// mov rax, qword ptr [rax+8]
+fn write_load_ob_type_rax(buf: *mut u8, index: usize) -> usize {
+    write_bytes(buf, index, &[0x48, 0x8b, 0x40, 0x08])
+}
+
+// test dword ptr [rax+tp_flags], flags
+fn write_test_tp_flags_rax(buf: *mut u8, index: usize, flags: u32) -> usize {
+    let tp_flags = std::mem::offset_of!(PyTypeObject, tp_flags) as u32;
+    let index = write_bytes(buf, index, &[0xf7, 0x80]);
+    let index = write_bytes(buf, index, &tp_flags.to_le_bytes());
+    write_bytes(buf, index, &flags.to_le_bytes())
+}
+
+// cmp qword ptr [rax+8], rcx
+fn write_cmp_ob_type_rcx(buf: *mut u8, index: usize) -> usize {
+    write_bytes(buf, index, &[0x48, 0x39, 0x48, 0x08])
+}
+
+// mov rax, qword ptr [rax+disp8]
+fn write_load_rax_field(buf: *mut u8, index: usize, disp: u8) -> usize {
+    write_bytes(buf, index, &[0x48, 0x8b, 0x40, disp])
+}
+
+// Unboxes a PyLongObject in RAX with at most one digit, whose sign and digit count is ob_size.
+// Jumps if the object has more digits.
+//   mov rcx, qword ptr [rax+16]
+//   lea rdx, [rcx+1]
+//   cmp rdx, 2
+//   ja  <rel32>
+//   mov eax, dword ptr [rax+24]
+//   imul rax, rcx
+// Returns the index after the ja so that the caller can patch it.
+fn write_unbox_compact_long(buf: *mut u8, index: usize) -> (usize, usize) {
+    let index = write_bytes(
+        buf,
+        index,
+        &[
+            0x48, 0x8b, 0x48, 0x10, 0x48, 0x8d, 0x51, 0x01, 0x48, 0x83, 0xfa, 0x02,
+        ],
     );
-    unsafe {
-        frame.read().f_localsplus[0] = arg;
-    };
-    unsafe {
-        let f_localsplus_head = &mut frame.read().f_localsplus;
-        info!(
-            "f_localsplus_head[0] = {:?} arg = {:?}",
-            f_localsplus_head[0], arg
-        );
-        f_localsplus_head[0] = arg;
-        info!(
-            "f_localsplus_head[0] = {:?} arg = {:?}",
-            f_localsplus_head[0], arg
-        );
-    };
-    info!(
-        "frame.read().f_localsplus[0] = {:?} arg = {:?}",
-        unsafe { frame.read().f_localsplus[0] },
-        arg
+    let index = write_ja(buf, index, 0);
+    let jump_end = index;
+    let index = write_bytes(buf, index, &[0x8b, 0x40, 0x18, 0x48, 0x0f, 0xaf, 0xc1]);
+    (index, jump_end)
+}
+
+// add rax, rcx
+fn write_add_rax_rcx(buf: *mut u8, index: usize) -> usize {
+    write_bytes(buf, index, &[0x48, 0x01, 0xc8])
+}
+
+// sub rax, rcx
+fn write_sub_rax_rcx(buf: *mut u8, index: usize) -> usize {
+    write_bytes(buf, index, &[0x48, 0x29, 0xc8])
+}
+
+// imul rax, rcx
+fn write_imul_rax_rcx(buf: *mut u8, index: usize) -> usize {
+    write_bytes(buf, index, &[0x48, 0x0f, 0xaf, 0xc1])
+}
+
+// movq xmm0, rax
+fn write_movq_xmm0_rax(buf: *mut u8, index: usize) -> usize {
+    write_bytes(buf, index, &[0x66, 0x48, 0x0f, 0x6e, 0xc0])
+}
+
+// movq xmm1, rcx
+fn write_movq_xmm1_rcx(buf: *mut u8, index: usize) -> usize {
+    write_bytes(buf, index, &[0x66, 0x48, 0x0f, 0x6e, 0xc9])
+}
+
+// cvtsi2sd xmm0, rax
+fn write_cvtsi2sd_xmm0_rax(buf: *mut u8, index: usize) -> usize {
+    write_bytes(buf, index, &[0xf2, 0x48, 0x0f, 0x2a, 0xc0])
+}
+
+// cvtsi2sd xmm1, rcx
+fn write_cvtsi2sd_xmm1_rcx(buf: *mut u8, index: usize) -> usize {
+    write_bytes(buf, index, &[0xf2, 0x48, 0x0f, 0x2a, 0xc9])
+}
+
+// Jumps unless -2**53 <= reg < 2**53, i.e. unless the int converts to a double exactly.
+//   mov rdx, 2**53
+//   add rdx, <rax or rcx>
+//   shr rdx, 54
+//   jnz <rel32>
+fn write_check_exact_double(buf: *mut u8, index: usize, rcx: bool) -> usize {
+    let index = write_bytes(buf, index, &[0x48, 0xba]);
+    let index = write_bytes(buf, index, &(1u64 << 53).to_le_bytes());
+    let index = write_bytes(buf, index, &[0x48, 0x01, if rcx { 0xca } else { 0xc2 }]);
+    let index = write_bytes(buf, index, &[0x48, 0xc1, 0xea, 0x36]);
+    write_jne(buf, index, 0)
+}
+
+// movq rax, xmm0
+fn write_movq_rax_xmm0(buf: *mut u8, index: usize) -> usize {
+    write_bytes(buf, index, &[0x66, 0x48, 0x0f, 0x7e, 0xc0])
+}
+
+// addsd xmm0, xmm1
+fn write_addsd(buf: *mut u8, index: usize) -> usize {
+    write_bytes(buf, index, &[0xf2, 0x0f, 0x58, 0xc1])
+}
+
+// subsd xmm0, xmm1
+fn write_subsd(buf: *mut u8, index: usize) -> usize {
+    write_bytes(buf, index, &[0xf2, 0x0f, 0x5c, 0xc1])
+}
+
+// mulsd xmm0, xmm1
+fn write_mulsd(buf: *mut u8, index: usize) -> usize {
+    write_bytes(buf, index, &[0xf2, 0x0f, 0x59, 0xc1])
+}
+
+// divsd xmm0, xmm1
+fn write_divsd(buf: *mut u8, index: usize) -> usize {
+    write_bytes(buf, index, &[0xf2, 0x0f, 0x5e, 0xc1])
+}
+
+// xorpd xmm2, xmm2
+// ucomisd xmm1, xmm2
+// je <rel32>
+fn write_je_xmm1_zero(buf: *mut u8, index: usize) -> usize {
+    let index = write_bytes(
+        buf,
+        index,
+        &[0x66, 0x0f, 0x57, 0xd2, 0x66, 0x0f, 0x2e, 0xca],
     );
+    write_je(buf, index, 0)
+}
 
-    info!("========== dump_frame_info ==========");
-    dump_frame_info(state, frame, 0);
-
-    jit_log!("start call_callable");
-    let args = unsafe { PyTuple_New(1) };
-    unsafe { PyTuple_SetItem(args, 0, arg) };
-    let kwargs = unsafe { PyDict_New() };
-    // let r: *mut PyObject = unsafe { PyObject_Call(callable, args, kwargs) };
-    let r: *mut PyObject = unsafe { eval(state, frame, 0) };
-    info!("end call_callable type:{:?}", get_type(r));
-    jit_log!("end call_callable");
-    r
+// cmp rax, rcx
+fn write_cmp_rax_rcx(buf: *mut u8, index: usize) -> usize {
+    write_bytes(buf, index, &[0x48, 0x39, 0xc8])
 }
 
-pub fn compile_and_exec_jit_code(
-    state: *mut PyThreadState,
-    frame: *mut PyFrameObject,
-    c: i32,
-) -> Option<*mut PyObject> {
-    info!("compile_and_exec_jit_code");
-    // dump_frame_info(state, frame, c);
+// ucomisd xmm0, xmm1
+fn write_ucomisd_xmm0_xmm1(buf: *mut u8, index: usize) -> usize {
+    write_bytes(buf, index, &[0x66, 0x0f, 0x2e, 0xc1])
+}
 
-    const CODE_AREA_SIZE: usize = 4096;
-    const PAGE_SIZE: usize = 4096;
+// ucomisd xmm1, xmm0
+fn write_ucomisd_xmm1_xmm0(buf: *mut u8, index: usize) -> usize {
+    write_bytes(buf, index, &[0x66, 0x0f, 0x2e, 0xc8])
+}
 
-    let layout = Layout::from_size_align(CODE_AREA_SIZE, PAGE_SIZE).unwrap();
-    let p_start = unsafe { alloc(layout) };
-    let mem = unsafe {
-        mprotect(
-            p_start as *const c_void,
-            CODE_AREA_SIZE,
-            PROT_READ | PROT_WRITE | PROT_EXEC,
-        )
-    };
-    assert_eq!(mem, 0);
-    let mut offset = 0;
+// set<cc> al, where cc is the low nibble of the 0f 9x opcode
+fn write_setcc_al(buf: *mut u8, index: usize, cc: u8) -> usize {
+    write_bytes(buf, index, &[0x0f, 0x90 | cc, 0xc0])
+}
 
-    // Write endbr64
-    offset = write_endbr64(p_start, offset);
+// set<cc> cl
+fn write_setcc_cl(buf: *mut u8, index: usize, cc: u8) -> usize {
+    write_bytes(buf, index, &[0x0f, 0x90 | cc, 0xc1])
+}
 
-    // Write push rbp
-    offset = write_push_rbp(p_start, offset);
+// and al, cl
+fn write_and_al_cl(buf: *mut u8, index: usize) -> usize {
+    write_bytes(buf, index, &[0x20, 0xc8])
+}
 
-    // Write mov rsp to rbp
-    offset = write_mov_rsp_to_rbp(p_start, offset);
+// or al, cl
+fn write_or_al_cl(buf: *mut u8, index: usize) -> usize {
+    write_bytes(buf, index, &[0x08, 0xc8])
+}
 
-    // Compile
-    {
-        let f_code = unsafe { frame.read().f_code.read().co_code };
-        let is_bytes = unsafe { PyBytes_Check(f_code) };
-        let n_bytes = unsafe { PyBytes_Size(f_code) };
-        info!("is_bytes:{:?} n_bytes:{:?}", is_bytes, n_bytes);
-
-        let code_buf = unsafe { PyBytes_AsString(f_code) };
-        let mut code_vec: Vec<u8> = Vec::new();
-        for i in 0..n_bytes {
-            unsafe { code_vec.push(*code_buf.offset(i as isize) as u8) };
-        }
-
-        // Show code
-        for i in (0..n_bytes).step_by(2) {
-            let code: Bytecode =
-                unsafe { num::FromPrimitive::from_u8(*code_buf.offset(i) as u8).unwrap() };
-            let arg: i8 = unsafe { *code_buf.offset(i as isize + 1) };
-            debug!("code_vec[{}]:{:?}, 0x{:02x?}", i, code, arg);
-        }
-
-        // Hack to realize relative jump in Python bytecode easily. All byte code is translated to
-        // x86_64 code with bytes_per_code bytes.
-        let bytes_per_code: u32 = 64;
-        assert!(
-            n_bytes % 2 == 0 && n_bytes / 2 * bytes_per_code as isize <= CODE_AREA_SIZE as isize
-        );
+// movzx eax, al
+fn write_movzx_eax_al(buf: *mut u8, index: usize) -> usize {
+    write_bytes(buf, index, &[0x0f, 0xb6, 0xc0])
+}
 
-        // Compile
-        for i in (0..n_bytes).step_by(2) {
-            let code: Bytecode =
-                unsafe { num::FromPrimitive::from_u8(*code_buf.offset(i) as u8).unwrap() };
-            let arg: i8 = unsafe { *code_buf.offset(i as isize + 1) };
-            let start_offset = offset;
-            match code {
-                Bytecode::LoadFast => {
-                    let l = unsafe { frame.read().f_localsplus[arg as usize] };
-                    // MOV RAX, l
-                    offset = write_mov_rax(p_start, offset, l as u64);
-                    // PUSH RAX
-                    offset = write_push_rax(p_start, offset);
-                }
-                Bytecode::ReturnValue => {
-                    // POP RAX
-                    offset = write_pop_rax(p_start, offset);
-                    // POP RBP
-                    // offset = write_pop_rbp(p_start, offset);
-                    // leave
-                    offset = write_leave(p_start, offset);
-                    // Breakpoint
-                    // offset = write_software_breakpoint(p_start, offset);
-                    // RET
-                    offset = write_ret(p_start, offset);
-                }
-                Bytecode::BinaryAdd => {
-                    // POP RDI
-                    offset = write_pop_rdi(p_start, offset);
-                    // POP RSI
-                    offset = write_pop_rsi(p_start, offset);
+// Replaces 0 or 1 in RAX by a new reference to Py_False or Py_True.
+//   test al, al
+//   mov rax, Py_False
+//   mov rcx, Py_True
+//   cmovne rax, rcx
+//   add qword ptr [rax], 1
+fn write_box_bool(buf: *mut u8, index: usize) -> usize {
+    let index = write_bytes(buf, index, &[0x84, 0xc0]);
+    let index = write_mov_rax(buf, index, unsafe { Py_False() } as u64);
+    let index = write_mov_rcx(buf, index, unsafe { Py_True() } as u64);
+    let index = write_bytes(buf, index, &[0x48, 0x0f, 0x45, 0xc1]);
+    write_incref_rax(buf, index)
+}
 
-                    // MOV $RAX, add_py_longs
-                    offset = write_mov_rax(p_start, offset, add_py_longs as u64);
-                    // CALL $RAX
-                    offset = write_call_rax(p_start, offset);
+// Condition codes of set<cc> and j<cc>
+const CC_NE: u8 = 0x5;
+const CC_A: u8 = 0x7;
+const CC_AE: u8 = 0x3;
+const CC_E: u8 = 0x4;
+const CC_P: u8 = 0xa;
+const CC_NP: u8 = 0xb;
+const CC_L: u8 = 0xc;
+const CC_GE: u8 = 0xd;
+const CC_LE: u8 = 0xe;
+const CC_G: u8 = 0xf;
 
-                    // PUSH RAX
-                    offset = write_push_rax(p_start, offset);
-                }
-                Bytecode::BinarySubtract => {
-                    // POP RSI
-                    offset = write_pop_rsi(p_start, offset);
-                    // POP RDI
-                    offset = write_pop_rdi(p_start, offset);
+fn write_mov_rdi(buf: *mut u8, index: usize, value: u64) -> usize {
+    let index = write_bytes(buf, index, &[0x48, 0xbf]);
+    write_bytes(buf, index, &value.to_le_bytes())
+}
 
-                    // MOV $RAX, sub_py_longs
-                    offset = write_mov_rax(p_start, offset, sub_py_longs as u64);
-                    // CALL $RAX
-                    offset = write_call_rax(p_start, offset);
+fn write_mov_esi(buf: *mut u8, index: usize, value: u32) -> usize {
+    let index = write_bytes(buf, index, &[0xbe]);
+    write_bytes(buf, index, &value.to_le_bytes())
+}
 
-                    // PUSH RAX
-                    offset = write_push_rax(p_start, offset);
-                }
-                Bytecode::CompareOp => {
-                    match arg {
-                        0 => {
-                            // POP RDI
-                            offset = write_pop_rdi(p_start, offset);
-                            // POP RSI
-                            offset = write_pop_rsi(p_start, offset);
-
-                            // MOV $RAX, sub_py_longs
-                            offset = write_mov_rax(p_start, offset, compare_py_longs as u64);
-                            // CALL $RAX
-                            offset = write_call_rax(p_start, offset);
-
-                            // PUSH RAX
-                            offset = write_push_rax(p_start, offset);
-                        }
-                        _ => {
-                            info!("Unknown code:{:?}", code);
-                            info!("Fallback to the Python interpreter");
-                            return None;
-                        }
-                    }
-                }
-                Bytecode::LoadConst => {
-                    let const_table = unsafe { frame.read().f_code.read().co_consts };
-                    let const_object = unsafe { PyTuple_GetItem(const_table, arg as isize) };
+fn write_mov_rsi(buf: *mut u8, index: usize, value: u64) -> usize {
+    let index = write_bytes(buf, index, &[0x48, 0xbe]);
+    write_bytes(buf, index, &value.to_le_bytes())
+}
 
-                    // MOV RAX, const_object
-                    offset = write_mov_rax(p_start, offset, const_object as u64);
-                    // PUSH RAX
-                    offset = write_push_rax(p_start, offset);
-                }
-                Bytecode::PopJumpIfFalse => {
-                    // POP RDI
-                    offset = write_pop_rdi(p_start, offset);
-                    // MOV $RAX, check_py_bool
-                    offset = write_mov_rax(p_start, offset, check_py_bool as u64);
-                    // CALL $RAX
-                    offset = write_call_rax(p_start, offset);
-
-                    // Now RAX is the result of check_py_bool
-                    // 1 is true, 0 is false
-                    // CMP RAX, 0
-                    offset = write_cmp_rax_0(p_start, offset);
-                    // JE
-                    offset = write_je(p_start, offset, ((arg / 2) as u32 * bytes_per_code) as i32);
-                }
-                Bytecode::LoadGlobal => {
-                    let name = unsafe {
-                        PyTuple_GetItem(frame.read().f_code.read().co_names, arg as isize)
-                    };
-                    let globals = unsafe { frame.read().f_globals };
-                    let value = unsafe { PyDict_GetItem(globals, name) };
-                    // MOV RAX, value
-                    offset = write_mov_rax(p_start, offset, value as u64);
-                    // PUSH RAX
-                    offset = write_push_rax(p_start, offset);
-                }
-                Bytecode::CallFunction => {
-                    assert_eq!(arg, 1, "Only support 1 argument function");
-                    // POP RSI
-                    offset = write_pop_rsi(p_start, offset);
-                    // POP RDI
-                    offset = write_pop_rdi(p_start, offset);
-                    // MOV $RAX, call_callable
-                    offset = write_mov_rax(p_start, offset, call_callable as u64);
-
-                    // CALL $RAX
-                    offset = write_call_rax(p_start, offset);
-                    // Breakpoint
-                    offset = write_software_breakpoint(p_start, offset);
-                    // PUSH RAX
-                    offset = write_push_rax(p_start, offset);
-                }
-                _ => {
-                    info!("Unknown code:{:?}", code);
-                    info!("Fallback to the Python interpreter");
-                    return None;
+fn write_mov_rdx(buf: *mut u8, index: usize, value: u64) -> usize {
+    let index = write_bytes(buf, index, &[0x48, 0xba]);
+    write_bytes(buf, index, &value.to_le_bytes())
+}
+
+// mov rdi, rsp
+fn write_mov_rsp_to_rdi(buf: *mut u8, index: usize) -> usize {
+    write_bytes(buf, index, &[0x48, 0x89, 0xe7])
+}
+
+// push 0
+fn write_push_null(buf: *mut u8, index: usize) -> usize {
+    write_bytes(buf, index, &[0x6a, 0x00])
+}
+
+// mov rcx, rbp
+fn write_mov_rbp_to_rcx(buf: *mut u8, index: usize) -> usize {
+    write_bytes(buf, index, &[0x48, 0x89, 0xe9])
+}
+
+// mov rsi, rbp
+fn write_mov_rbp_to_rsi(buf: *mut u8, index: usize) -> usize {
+    write_bytes(buf, index, &[0x48, 0x89, 0xee])
+}
+
+// cmp qword ptr [rax], rcx
+fn write_cmp_deref_rax_rcx(buf: *mut u8, index: usize) -> usize {
+    write_bytes(buf, index, &[0x48, 0x39, 0x08])
+}
+
+// cmp qword ptr [rcx], rax
+fn write_cmp_deref_rcx_rax(buf: *mut u8, index: usize) -> usize {
+    write_bytes(buf, index, &[0x48, 0x39, 0x01])
+}
+
+// lea rsp, [rbp-8*depth]
+fn write_reset_rsp(buf: *mut u8, index: usize, depth: usize) -> usize {
+    let index = write_bytes(buf, index, &[0x48, 0x8d, 0xa5]);
+    write_rel32(buf, index, -8 * depth as i32)
+}
+
+fn write_jmp(buf: *mut u8, index: usize, offset: i32) -> usize {
+    let index = write_bytes(buf, index, &[0xe9]);
+    write_rel32(buf, index, offset)
+}
+
+fn write_jne(buf: *mut u8, index: usize, offset: i32) -> usize {
+    let index = write_bytes(buf, index, &[0x0f, 0x85]);
+    write_rel32(buf, index, offset)
+}
+
+fn write_ja(buf: *mut u8, index: usize, offset: i32) -> usize {
+    let index = write_bytes(buf, index, &[0x0f, 0x87]);
+    write_rel32(buf, index, offset)
+}
+
+fn write_js(buf: *mut u8, index: usize, offset: i32) -> usize {
+    let index = write_bytes(buf, index, &[0x0f, 0x88]);
+    write_rel32(buf, index, offset)
+}
+
+fn write_jo(buf: *mut u8, index: usize, offset: i32) -> usize {
+    let index = write_bytes(buf, index, &[0x0f, 0x80]);
+    write_rel32(buf, index, offset)
+}
+
+/// Computes `a <op> b` for a `BinaryOp` argument. Consumes the references to `a` and `b`.
+fn binary_op(a: *mut PyObject, b: *mut PyObject, op: u32) -> *mut PyObject {
+    unsafe {
+        let r = match op {
+            NB_ADD => PyNumber_Add(a, b),
+            NB_AND => PyNumber_And(a, b),
+            NB_FLOOR_DIVIDE => PyNumber_FloorDivide(a, b),
+            NB_LSHIFT => PyNumber_Lshift(a, b),
+            NB_MATRIX_MULTIPLY => PyNumber_MatrixMultiply(a, b),
+            NB_MULTIPLY => PyNumber_Multiply(a, b),
+            NB_REMAINDER => PyNumber_Remainder(a, b),
+            NB_OR => PyNumber_Or(a, b),
+            NB_POWER => PyNumber_Power(a, b, Py_None()),
+            NB_RSHIFT => PyNumber_Rshift(a, b),
+            NB_SUBTRACT => PyNumber_Subtract(a, b),
+            NB_TRUE_DIVIDE => PyNumber_TrueDivide(a, b),
+            NB_XOR => PyNumber_Xor(a, b),
+            NB_INPLACE_ADD => PyNumber_InPlaceAdd(a, b),
+            NB_INPLACE_AND => PyNumber_InPlaceAnd(a, b),
+            NB_INPLACE_FLOOR_DIVIDE => PyNumber_InPlaceFloorDivide(a, b),
+            NB_INPLACE_LSHIFT => PyNumber_InPlaceLshift(a, b),
+            NB_INPLACE_MATRIX_MULTIPLY => PyNumber_InPlaceMatrixMultiply(a, b),
+            NB_INPLACE_MULTIPLY => PyNumber_InPlaceMultiply(a, b),
+            NB_INPLACE_REMAINDER => PyNumber_InPlaceRemainder(a, b),
+            NB_INPLACE_OR => PyNumber_InPlaceOr(a, b),
+            NB_INPLACE_POWER => PyNumber_InPlacePower(a, b, Py_None()),
+            NB_INPLACE_RSHIFT => PyNumber_InPlaceRshift(a, b),
+            NB_INPLACE_SUBTRACT => PyNumber_InPlaceSubtract(a, b),
+            NB_INPLACE_TRUE_DIVIDE => PyNumber_InPlaceTrueDivide(a, b),
+            NB_INPLACE_XOR => PyNumber_InPlaceXor(a, b),
+            _ => panic!("Unknown binary op:{:?}", op),
+        };
+        Py_DECREF(a);
+        Py_DECREF(b);
+        r
+    }
+}
+
+fn box_float(bits: u64) -> *mut PyObject {
+    unsafe { PyFloat_FromDouble(f64::from_bits(bits)) }
+}
+
+/// Computes `a <op> b` for a `CompareOp` argument, which is one of `Py_LT` to `Py_GE`. Consumes
+/// the references to `a` and `b`.
+fn compare_op(a: *mut PyObject, b: *mut PyObject, op: u32) -> *mut PyObject {
+    unsafe {
+        let r = PyObject_RichCompare(a, b, op as c_int);
+        Py_DECREF(a);
+        Py_DECREF(b);
+        r
+    }
+}
+
+/// Computes `-a`, `~a` or `not a` for the unary instruction `op`. Consumes the reference to `a`.
+fn unary_op(a: *mut PyObject, op: u32) -> *mut PyObject {
+    unsafe {
+        let r = if op == Bytecode::UnaryNegative as u32 {
+            PyNumber_Negative(a)
+        } else if op == Bytecode::UnaryInvert as u32 {
+            PyNumber_Invert(a)
+        } else {
+            match PyObject_IsTrue(a) {
+                -1 => std::ptr::null_mut(),
+                truth => {
+                    let r = if truth == 0 { Py_True() } else { Py_False() };
+                    Py_INCREF(r);
+                    r
                 }
             }
-            while (offset - start_offset) < bytes_per_code.try_into().unwrap() {
-                offset = write_nop(p_start, offset);
-            }
-            assert_eq!(offset - start_offset, bytes_per_code.try_into().unwrap());
-        }
-        if std::env::var("RUST_LOG") == Result::Ok(String::from("debug")) {
-            log_disasm(p_start, offset, code_vec, bytes_per_code);
+        };
+        Py_DECREF(a);
+        r
+    }
+}
+
+/// Computes `item in container`, or `item not in container` if `invert` is 1. Consumes the
+/// references to both.
+fn contains_op(item: *mut PyObject, container: *mut PyObject, invert: u32) -> *mut PyObject {
+    unsafe {
+        let ty = Py_TYPE(container);
+        let r = if ty == std::ptr::addr_of_mut!(PyDict_Type) {
+            PyDict_Contains(container, item)
+        } else if ty == std::ptr::addr_of_mut!(PySet_Type)
+            || ty == std::ptr::addr_of_mut!(PyFrozenSet_Type)
+        {
+            PySet_Contains(container, item)
+        } else {
+            PySequence_Contains(container, item)
+        };
+        Py_DECREF(item);
+        Py_DECREF(container);
+        if r < 0 {
+            return std::ptr::null_mut();
         }
+        let r = if (r == 1) != (invert == 1) {
+            Py_True()
+        } else {
+            Py_False()
+        };
+        Py_INCREF(r);
+        r
     }
-    let code: fn() -> *mut PyObject = unsafe { std::mem::transmute(p_start) };
+}
 
-    info!("Jump to code:{:x?}", code);
-    let retval = code();
-    info!(
-        "Return from code:{:x?} retval:{:x?} PyLong_AsLong(retval):{:x?}",
-        code,
-        retval,
-        unsafe { PyLong_AsLong(retval) },
-    );
-    info!("type(retval):{:?}", get_type(retval));
-    return Some(retval);
+/// Returns 1 if `a` is true, 0 if false and -1 with an exception set. Consumes the reference to
+/// `a`.
+fn object_is_true(a: *mut PyObject) -> i64 {
+    unsafe {
+        let r = PyObject_IsTrue(a);
+        Py_DECREF(a);
+        r as i64
+    }
 }
 
-fn log_disasm(code: *const u8, code_size: usize, py_code_vec: Vec<u8>, bytes_per_code: u32) {
-    assert_eq!(py_code_vec.len() % 2, 0);
-    assert!(
-        code_size >= py_code_vec.len() / 2 * bytes_per_code as usize,
-        "code_size:{:?} py_code_vec.len():{:?} bytes_per_code:{:?}",
-        code_size,
-        py_code_vec.len(),
-        bytes_per_code
-    );
+/// Returns 1 if `a` is true, 0 if false and -1 with an exception set.
+fn object_truth(a: *mut PyObject) -> i64 {
+    unsafe { PyObject_IsTrue(a) as i64 }
+}
 
-    let mut code_vec: Vec<u8> = Vec::new();
-    for i in 0..code_size {
-        code_vec.push(unsafe { *code.offset(i as isize) });
+/// Looks up a global by name in the frame's globals and then its builtins, raising NameError
+/// if neither has it. Returns a new reference.
+fn load_global(frame: *mut PyFrameObject, name: *mut PyObject) -> *mut PyObject {
+    unsafe {
+        let mut value = PyDict_GetItemWithError((*frame).f_globals, name);
+        if value.is_null() && PyErr_Occurred().is_null() {
+            value = PyDict_GetItemWithError((*frame).f_builtins, name);
+            if value.is_null() && PyErr_Occurred().is_null() {
+                name_error(name);
+            }
+        }
+        if !value.is_null() {
+            Py_INCREF(value);
+        }
+        value
     }
-    let bytes = code_vec.as_slice();
-    let bitness = 64;
-    let rip = code as u64;
-    const HEXBYTES_COLUMN_BYTE_LENGTH: usize = 10;
-    let mut decoder = Decoder::with_ip(bitness, code_vec.as_slice(), rip, DecoderOptions::NONE);
+}
 
-    // Formatters: Masm*, Nasm*, Gas* (AT&T) and Intel* (XED).
-    // For fastest code, see `SpecializedFormatter` which is ~3.3x faster. Use it if formatting
-    // speed is more important than being able to re-assemble formatted instructions.
-    let mut formatter = IntelFormatter::new();
+/// A global which folded instructions depend on. The version tag of the globals dict is compared
+/// first, and only when it has changed is the name looked up again.
+struct GlobalGuard {
+    /// The version of `globals` at which `name` was last seen bound to `value`
+    version: u64,
+    globals: *mut PyObject,
+    name: *mut PyObject,
+    /// The value the instructions were folded with, which the guard keeps alive
+    value: *mut PyObject,
+}
 
-    // Change some options, there are many more
-    formatter.options_mut().set_digit_separator("`");
-    formatter.options_mut().set_first_operand_char_index(10);
+impl GlobalGuard {
+    fn new(globals: *mut PyObject, name: *mut PyObject, value: *mut PyObject) -> GlobalGuard {
+        unsafe { Py_INCREF(value) };
+        GlobalGuard {
+            version: dict_version(globals),
+            globals,
+            name,
+            value,
+        }
+    }
+}
 
-    // String implements FormatterOutput
-    let mut output = String::new();
+impl Drop for GlobalGuard {
+    fn drop(&mut self) {
+        unsafe { Py_DECREF(self.value) };
+    }
+}
+
+/// Returns 1 and records the current version of the globals dict if the guarded global is still
+/// bound to the same object, or 0.
+fn global_unchanged(guard: *mut GlobalGuard) -> i64 {
+    unsafe {
+        let guard = &mut *guard;
+        if PyDict_GetItem(guard.globals, guard.name) != guard.value {
+            return 0;
+        }
+        guard.version = dict_version(guard.globals);
+        1
+    }
+}
+
+/// A str interned on first use and kept from then on, like `_Py_IDENTIFIER` of CPython.
+struct Identifier {
+    name: &'static CStr,
+    object: AtomicPtr<PyObject>,
+}
+
+impl Identifier {
+    const fn new(name: &'static CStr) -> Identifier {
+        Identifier {
+            name,
+            object: AtomicPtr::new(std::ptr::null_mut()),
+        }
+    }
+
+    /// Returns a borrowed reference to the str, or NULL with an exception set.
+    unsafe fn get(&self) -> *mut PyObject {
+        let mut object = self.object.load(Ordering::Relaxed);
+        if object.is_null() {
+            object = PyUnicode_InternFromString(self.name.as_ptr());
+            self.object.store(object, Ordering::Relaxed);
+        }
+        object
+    }
+}
+
+static ID_NAME: Identifier = Identifier::new(c"name");
+static ID_ANNOTATIONS: Identifier = Identifier::new(c"__annotations__");
+static ID_BUILD_CLASS: Identifier = Identifier::new(c"__build_class__");
+static ID_IMPORT: Identifier = Identifier::new(c"__import__");
+static ID_ENTER: Identifier = Identifier::new(c"__enter__");
+static ID_EXIT: Identifier = Identifier::new(c"__exit__");
+static ID_ALL: Identifier = Identifier::new(c"__all__");
+static ID_DICT: Identifier = Identifier::new(c"__dict__");
+
+/// Raises NameError for `name`, which is kept as the `name` attribute for the suggestions of the
+/// traceback.
+unsafe fn name_error(name: *mut PyObject) {
+    PyErr_Format(PyExc_NameError, c"name '%U' is not defined".as_ptr(), name);
+    let mut exc = std::ptr::null_mut();
+    let mut val = std::ptr::null_mut();
+    let mut tb = std::ptr::null_mut();
+    PyErr_Fetch(&mut exc, &mut val, &mut tb);
+    PyErr_NormalizeException(&mut exc, &mut val, &mut tb);
+    if PyErr_GivenExceptionMatches(val, PyExc_NameError) != 0 {
+        let attr = ID_NAME.get();
+        if attr.is_null() || PyObject_SetAttr(val, attr, name) < 0 {
+            PyErr_Clear();
+        }
+    }
+    PyErr_Restore(exc, val, tb);
+}
+
+/// Looks up a name in the frame's locals, then its globals and then its builtins, raising
+/// NameError if none has it. Returns a new reference.
+fn load_name(frame: *mut PyFrameObject, name: *mut PyObject) -> *mut PyObject {
+    unsafe {
+        let locals = (*frame).f_locals;
+        if locals.is_null() {
+            PyErr_Format(
+                PyExc_SystemError,
+                c"no locals when loading %R".as_ptr(),
+                name,
+            );
+            return std::ptr::null_mut();
+        }
+        let value = get_item_or_null(locals, name);
+        if !value.is_null() || !PyErr_Occurred().is_null() {
+            return value;
+        }
+        let value = PyDict_GetItemWithError((*frame).f_globals, name);
+        if !value.is_null() {
+            Py_INCREF(value);
+            return value;
+        }
+        if !PyErr_Occurred().is_null() {
+            return value;
+        }
+        let value = get_item_or_null((*frame).f_builtins, name);
+        if value.is_null() && PyErr_Occurred().is_null() {
+            name_error(name);
+        }
+        value
+    }
+}
+
+/// Returns a new reference to `mapping[key]`, or NULL without an exception if it is missing.
+unsafe fn get_item_or_null(mapping: *mut PyObject, key: *mut PyObject) -> *mut PyObject {
+    if Py_TYPE(mapping) == std::ptr::addr_of_mut!(PyDict_Type) {
+        let value = PyDict_GetItemWithError(mapping, key);
+        Py_XINCREF(value);
+        value
+    } else {
+        let value = PyObject_GetItem(mapping, key);
+        if value.is_null() && PyErr_ExceptionMatches(PyExc_KeyError) != 0 {
+            PyErr_Clear();
+        }
+        value
+    }
+}
+
+/// Stores `value` as `name` in the frame's locals, stealing the reference.
+fn store_name(frame: *mut PyFrameObject, name: *mut PyObject, value: *mut PyObject) -> i64 {
+    unsafe {
+        let locals = (*frame).f_locals;
+        let r = if locals.is_null() {
+            PyErr_Format(
+                PyExc_SystemError,
+                c"no locals found when storing %R".as_ptr(),
+                name,
+            );
+            -1
+        } else if Py_TYPE(locals) == std::ptr::addr_of_mut!(PyDict_Type) {
+            PyDict_SetItem(locals, name, value)
+        } else {
+            PyObject_SetItem(locals, name, value)
+        };
+        Py_DECREF(value);
+        r as i64
+    }
+}
+
+/// Deletes `name` from the frame's locals, raising NameError if it is missing.
+fn delete_name(frame: *mut PyFrameObject, name: *mut PyObject) -> i64 {
+    unsafe {
+        let locals = (*frame).f_locals;
+        if locals.is_null() {
+            PyErr_Format(
+                PyExc_SystemError,
+                c"no locals when deleting %R".as_ptr(),
+                name,
+            );
+            return -1;
+        }
+        if PyObject_DelItem(locals, name) < 0 {
+            name_error(name);
+            return -1;
+        }
+        0
+    }
+}
+
+/// Creates the `__annotations__` dict in the frame's locals unless it exists.
+fn setup_annotations(frame: *mut PyFrameObject) -> i64 {
+    unsafe {
+        let locals = (*frame).f_locals;
+        if locals.is_null() {
+            PyErr_SetString(
+                PyExc_SystemError,
+                c"no locals found when setting up annotations".as_ptr(),
+            );
+            return -1;
+        }
+        let name = ID_ANNOTATIONS.get();
+        if name.is_null() {
+            return -1;
+        }
+        let annotations = get_item_or_null(locals, name);
+        let r = if !annotations.is_null() {
+            Py_DECREF(annotations);
+            0
+        } else if !PyErr_Occurred().is_null() {
+            -1
+        } else {
+            let annotations = PyDict_New();
+            let r = if annotations.is_null() {
+                -1
+            } else if Py_TYPE(locals) == std::ptr::addr_of_mut!(PyDict_Type) {
+                PyDict_SetItem(locals, name, annotations)
+            } else {
+                PyObject_SetItem(locals, name, annotations)
+            };
+            Py_XDECREF(annotations);
+            r
+        };
+        r as i64
+    }
+}
+
+/// Stores `value` as the global `name`, stealing the reference.
+fn store_global(frame: *mut PyFrameObject, name: *mut PyObject, value: *mut PyObject) -> i64 {
+    unsafe {
+        let r = PyDict_SetItem((*frame).f_globals, name, value);
+        Py_DECREF(value);
+        r as i64
+    }
+}
+
+/// Deletes the global `name`, raising NameError if it is missing.
+fn delete_global(frame: *mut PyFrameObject, name: *mut PyObject) -> i64 {
+    unsafe {
+        if PyDict_DelItem((*frame).f_globals, name) < 0 {
+            if PyErr_ExceptionMatches(PyExc_KeyError) != 0 {
+                PyErr_Clear();
+                name_error(name);
+            }
+            return -1;
+        }
+        0
+    }
+}
+
+/// Raises UnboundLocalError for the i-th local.
+fn unbound_local(frame: *mut PyFrameObject, i: u32) {
+    unsafe {
+        let name = PyTuple_GetItem((*(*frame).f_code).co_varnames, i as isize);
+        PyErr_Format(
+            PyExc_UnboundLocalError,
+            c"local variable '%U' referenced before assignment".as_ptr(),
+            name,
+        );
+    }
+}
+
+/// Raises for the i-th cell or free variable being empty.
+fn unbound_deref(frame: *mut PyFrameObject, i: u32) {
+    unsafe {
+        let code = (*frame).f_code;
+        let n_cells = PyTuple_Size((*code).co_cellvars);
+        if (i as isize) < n_cells {
+            let name = PyTuple_GetItem((*code).co_cellvars, i as isize);
+            PyErr_Format(
+                PyExc_UnboundLocalError,
+                c"local variable '%U' referenced before assignment".as_ptr(),
+                name,
+            );
+        } else {
+            let name = PyTuple_GetItem((*code).co_freevars, i as isize - n_cells);
+            PyErr_Format(
+                PyExc_NameError,
+                c"free variable '%U' referenced before assignment in enclosing scope".as_ptr(),
+                name,
+            );
+        }
+    }
+}
+
+/// Loads a free variable of a class body, which the class namespace may shadow. Returns a new
+/// reference or NULL with an exception set.
+fn load_class_deref(frame: *mut PyFrameObject, i: u32) -> *mut PyObject {
+    unsafe {
+        let code = (*frame).f_code;
+        let locals = (*frame).f_locals;
+        let n_cells = PyTuple_Size((*code).co_cellvars);
+        let name = PyTuple_GetItem((*code).co_freevars, i as isize - n_cells);
+        let value = if Py_TYPE(locals) == std::ptr::addr_of_mut!(PyDict_Type) {
+            let value = PyDict_GetItemWithError(locals, name);
+            Py_XINCREF(value);
+            value
+        } else {
+            let value = PyObject_GetItem(locals, name);
+            if value.is_null() && PyErr_ExceptionMatches(PyExc_KeyError) != 0 {
+                PyErr_Clear();
+            }
+            value
+        };
+        if !value.is_null() || !PyErr_Occurred().is_null() {
+            return value;
+        }
+        let slot = std::ptr::addr_of_mut!((*frame).f_localsplus) as *mut *mut PyObject;
+        let cell = *slot.add((*code).co_nlocals as usize + i as usize) as *mut PyCellObject;
+        let value = (*cell).ob_ref;
+        if value.is_null() {
+            unbound_deref(frame, i);
+        } else {
+            Py_INCREF(value);
+        }
+        value
+    }
+}
+
+// Flags of `MAKE_FUNCTION`, each selecting an attribute of the new function
+const FUNCTION_DEFAULTS: u64 = 1;
+const FUNCTION_KWDEFAULTS: u64 = 2;
+const FUNCTION_ANNOTATIONS: u64 = 4;
+const FUNCTION_CLOSURE: u64 = 8;
+
+/// Stores `value` as the attribute of `func` named by `flag`, stealing the reference.
+fn set_function_attribute(func: *mut PyObject, flag: u64, value: *mut PyObject) {
+    unsafe {
+        let func = func as *mut PyFunctionObject;
+        let slot = match flag {
+            FUNCTION_DEFAULTS => std::ptr::addr_of_mut!((*func).func_defaults),
+            FUNCTION_KWDEFAULTS => std::ptr::addr_of_mut!((*func).func_kwdefaults),
+            FUNCTION_ANNOTATIONS => std::ptr::addr_of_mut!((*func).func_annotations),
+            _ => std::ptr::addr_of_mut!((*func).func_closure),
+        };
+        let old = std::mem::replace(&mut *slot, value);
+        Py_XDECREF(old);
+    }
+}
+
+/// Creates a function from the qualified name on top of the stack and the code object below
+/// it. The attributes selected by `flags` are below them, the closure first.
+fn make_function(sp: *const *mut PyObject, flags: u64, frame: *mut PyFrameObject) -> *mut PyObject {
+    unsafe {
+        let qualname = *sp;
+        let code = *sp.add(1);
+        let func = PyFunction_NewWithQualName(code, (*frame).f_globals, qualname);
+        Py_DECREF(qualname);
+        Py_DECREF(code);
+        let mut k = 2;
+        for flag in [
+            FUNCTION_CLOSURE,
+            FUNCTION_ANNOTATIONS,
+            FUNCTION_KWDEFAULTS,
+            FUNCTION_DEFAULTS,
+        ] {
+            if flags & flag == 0 {
+                continue;
+            }
+            let value = *sp.add(k);
+            k += 1;
+            if func.is_null() {
+                Py_DECREF(value);
+            } else {
+                set_function_attribute(func, flag, value);
+            }
+        }
+        func
+    }
+}
+
+/// Returns a new reference to `__build_class__` of the builtins, or NULL with an exception set.
+fn load_build_class(frame: *mut PyFrameObject) -> *mut PyObject {
+    unsafe {
+        let builtins = (*frame).f_builtins;
+        let name = ID_BUILD_CLASS.get();
+        if name.is_null() {
+            return std::ptr::null_mut();
+        }
+        let value = if Py_TYPE(builtins) == std::ptr::addr_of_mut!(PyDict_Type) {
+            let value = PyDict_GetItemWithError(builtins, name);
+            Py_XINCREF(value);
+            value
+        } else {
+            let value = PyObject_GetItem(builtins, name);
+            if value.is_null() && PyErr_ExceptionMatches(PyExc_KeyError) != 0 {
+                PyErr_Clear();
+            }
+            value
+        };
+        if value.is_null() && PyErr_Occurred().is_null() {
+            PyErr_SetString(PyExc_NameError, c"__build_class__ not found".as_ptr());
+        }
+        value
+    }
+}
+
+/// Returns whether `func` is the `__import__` of the builtins module, which is the default import
+/// function of the interpreter unless the program replaced it.
+fn is_builtin_import(func: *mut PyObject) -> bool {
+    unsafe {
+        if PyCFunction_Check(func) == 0 {
+            return false;
+        }
+        let func = func as *mut PyCFunctionObject;
+        let module = (*func).m_self;
+        if CStr::from_ptr((*(*func).m_ml).ml_name).to_bytes() != b"__import__"
+            || module.is_null()
+            || PyModule_Check(module) == 0
+        {
+            return false;
+        }
+        let name = PyModule_GetName(module);
+        if name.is_null() {
+            PyErr_Clear();
+            return false;
+        }
+        CStr::from_ptr(name).to_bytes() == b"builtins"
+    }
+}
+
+/// Imports the module named by the i-th name with the fromlist on top of the stack and the level
+/// below it, calling `__import__` of the builtins if it is overridden.
+fn import_name(sp: *const *mut PyObject, i: u64, frame: *mut PyFrameObject) -> *mut PyObject {
+    unsafe {
+        let fromlist = *sp;
+        let level = *sp.add(1);
+        let name = PyTuple_GetItem((*(*frame).f_code).co_names, i as isize);
+        let locals = if (*frame).f_locals.is_null() {
+            Py_None()
+        } else {
+            (*frame).f_locals
+        };
+        let import_func = ID_IMPORT.get();
+        let import_func = if import_func.is_null() {
+            import_func
+        } else {
+            let func = PyDict_GetItemWithError((*frame).f_builtins, import_func);
+            if func.is_null() && PyErr_Occurred().is_null() {
+                PyErr_SetString(PyExc_ImportError, c"__import__ not found".as_ptr());
+            }
+            func
+        };
+        let r = if import_func.is_null() {
+            std::ptr::null_mut()
+        } else if is_builtin_import(import_func) {
+            let level = _PyLong_AsInt(level);
+            if level == -1 && !PyErr_Occurred().is_null() {
+                std::ptr::null_mut()
+            } else {
+                PyImport_ImportModuleLevelObject(name, (*frame).f_globals, locals, fromlist, level)
+            }
+        } else {
+            Py_INCREF(import_func);
+            let argv = [name, (*frame).f_globals, locals, fromlist, level];
+            let r = PyObject_VectorcallDict(import_func, argv.as_ptr(), 5, std::ptr::null_mut());
+            Py_DECREF(import_func);
+            r
+        };
+        Py_DECREF(fromlist);
+        Py_DECREF(level);
+        r
+    }
+}
+
+/// Fetches the i-th name from the module on top of the stack, falling back to its submodule of
+/// that name, and raises ImportError if neither exists.
+fn import_from(sp: *const *mut PyObject, i: u64, frame: *mut PyFrameObject) -> *mut PyObject {
+    unsafe {
+        let module = *sp;
+        let name = PyTuple_GetItem((*(*frame).f_code).co_names, i as isize);
+        let mut value = std::ptr::null_mut();
+        if _PyObject_LookupAttr(module, name, &mut value) != 0 {
+            return value;
+        }
+        let mut pkgname = PyObject_GetAttrString(module, c"__name__".as_ptr());
+        if !pkgname.is_null() && PyUnicode_Check(pkgname) != 0 {
+            let fullname = PyUnicode_FromFormat(c"%U.%U".as_ptr(), pkgname, name);
+            if fullname.is_null() {
+                Py_DECREF(pkgname);
+                return std::ptr::null_mut();
+            }
+            let value = PyImport_GetModule(fullname);
+            Py_DECREF(fullname);
+            if !value.is_null() || !PyErr_Occurred().is_null() {
+                Py_DECREF(pkgname);
+                return value;
+            }
+        } else {
+            Py_XDECREF(pkgname);
+            pkgname = std::ptr::null_mut();
+        }
+        let pkgpath = PyModule_GetFilenameObject(module);
+        let pkgname_or_unknown = if pkgname.is_null() {
+            let unknown = PyUnicode_FromString(c"<unknown module name>".as_ptr());
+            if unknown.is_null() {
+                Py_XDECREF(pkgpath);
+                return std::ptr::null_mut();
+            }
+            unknown
+        } else {
+            pkgname
+        };
+        if pkgpath.is_null() || PyUnicode_Check(pkgpath) == 0 {
+            PyErr_Clear();
+            let message = PyUnicode_FromFormat(
+                c"cannot import name %R from %R (unknown location)".as_ptr(),
+                name,
+                pkgname_or_unknown,
+            );
+            PyErr_SetImportError(message, pkgname, std::ptr::null_mut());
+            Py_XDECREF(message);
+        } else {
+            let spec = PyObject_GetAttrString(module, c"__spec__".as_ptr());
+            let format = if _PyModuleSpec_IsInitializing(spec) != 0 {
+                c"cannot import name %R from partially initialized module %R (most likely due to a \
+                 circular import) (%S)"
+            } else {
+                c"cannot import name %R from %R (%S)"
+            };
+            Py_XDECREF(spec);
+            let message = PyUnicode_FromFormat(format.as_ptr(), name, pkgname_or_unknown, pkgpath);
+            PyErr_SetImportError(message, pkgname, pkgpath);
+            Py_XDECREF(message);
+        }
+        Py_DECREF(pkgname_or_unknown);
+        Py_XDECREF(pkgpath);
+        std::ptr::null_mut()
+    }
+}
+
+fn dealloc(a: *mut PyObject) {
+    unsafe { _Py_Dealloc(a) };
+}
+
+fn decref(a: *mut PyObject) {
+    unsafe { Py_DECREF(a) };
+}
+
+/// Records that an exception was raised at the `index`-th instruction by adding the frame to the
+/// traceback, as the interpreter does before unwinding.
+fn raise_here(frame: *mut PyFrameObject, index: u32) {
+    unsafe {
+        (*frame).f_lasti = index as c_int;
+        PyTraceBack_Here(frame);
+    }
+}
+
+/// Releases the values of the native value stack from `sp` down to the `level`-th one. Values
+/// may be NULL.
+fn unwind_stack(sp: *const *mut PyObject, rbp: *const *mut PyObject, level: u64) {
+    unsafe {
+        let mut p = sp;
+        while p < rbp.sub(level as usize) {
+            Py_XDECREF(*p);
+            p = p.add(1);
+        }
+    }
+}
+
+/// Leaves an except handler whose block was set up with `level` values: releases the values
+/// above the three saved by `enter_handler` and restores the exception being handled before.
+fn unwind_except_handler(sp: *const *mut PyObject, rbp: *const *mut PyObject, level: u64) {
+    unsafe {
+        unwind_stack(sp, rbp, level + 3);
+        let saved = rbp.sub(level as usize + 3);
+        PyErr_SetExcInfo(*saved, *saved.add(1), *saved.add(2));
+    }
+}
+
+/// Enters an exception handler with the raised exception. Writes the exception being handled
+/// before and the raised one to the six values at `sp`, i.e. `sp[0]` is the raised type, as
+/// `SETUP_FINALLY` handlers expect, and makes the raised exception the one being handled.
+fn enter_handler(sp: *mut *mut PyObject) {
+    unsafe {
+        let mut exc = std::ptr::null_mut();
+        let mut val = std::ptr::null_mut();
+        let mut tb = std::ptr::null_mut();
+        PyErr_GetExcInfo(&mut exc, &mut val, &mut tb);
+        if exc.is_null() {
+            exc = Py_None();
+            Py_INCREF(exc);
+        }
+        *sp.add(3) = exc;
+        *sp.add(4) = val;
+        *sp.add(5) = tb;
+
+        let mut exc = std::ptr::null_mut();
+        let mut val = std::ptr::null_mut();
+        let mut tb = std::ptr::null_mut();
+        PyErr_Fetch(&mut exc, &mut val, &mut tb);
+        PyErr_NormalizeException(&mut exc, &mut val, &mut tb);
+        PyException_SetTraceback(val, if tb.is_null() { Py_None() } else { tb });
+        Py_INCREF(exc);
+        Py_INCREF(val);
+        Py_XINCREF(tb);
+        PyErr_SetExcInfo(exc, val, tb);
+        if tb.is_null() {
+            tb = Py_None();
+            Py_INCREF(tb);
+        }
+        *sp = exc;
+        *sp.add(1) = val;
+        *sp.add(2) = tb;
+    }
+}
+
+/// `POP_EXCEPT`: restores the exception being handled from the top three values.
+fn pop_except(sp: *const *mut PyObject) {
+    unsafe { PyErr_SetExcInfo(*sp, *sp.add(1), *sp.add(2)) };
+}
+
+/// `RERAISE`: raises the exception in the top three values again.
+fn reraise(sp: *const *mut PyObject) {
+    unsafe { PyErr_Restore(*sp, *sp.add(1), *sp.add(2)) };
+}
+
+/// Checks whether the exception type `exc` matches `class`, which is an exception class or a
+/// tuple of them. Returns 1 or 0, or -1 with an exception set. Consumes both references.
+fn exc_match(exc: *mut PyObject, class: *mut PyObject) -> i64 {
+    unsafe {
+        let classes = if PyTuple_Check(class) != 0 {
+            (0..PyTuple_Size(class))
+                .map(|i| PyTuple_GetItem(class, i))
+                .collect()
+        } else {
+            vec![class]
+        };
+        let r = if classes.iter().all(|c| PyExceptionClass_Check(*c) != 0) {
+            PyErr_GivenExceptionMatches(exc, class) as i64
+        } else {
+            PyErr_SetString(
+                PyExc_TypeError,
+                c"catching classes that do not inherit from BaseException is not allowed".as_ptr(),
+            );
+            -1
+        };
+        Py_DECREF(exc);
+        Py_DECREF(class);
+        r
+    }
+}
+
+/// Instantiates an exception class, or returns an exception instance as is. Returns a new
+/// reference or NULL with an exception set. Consumes the reference to `exc`.
+unsafe fn exception_instance(exc: *mut PyObject) -> *mut PyObject {
+    if PyExceptionClass_Check(exc) != 0 {
+        let value = PyObject_CallNoArgs(exc);
+        if !value.is_null() && PyExceptionInstance_Check(value) == 0 {
+            PyErr_Format(
+                PyExc_TypeError,
+                c"calling %R should have returned an instance of BaseException, not %R".as_ptr(),
+                exc,
+                Py_TYPE(value),
+            );
+            Py_DECREF(value);
+            Py_DECREF(exc);
+            return std::ptr::null_mut();
+        }
+        Py_DECREF(exc);
+        value
+    } else {
+        exc
+    }
+}
+
+/// `RAISE_VARARGS`: raises the exception in the top `n` values, i.e. `raise`, `raise exc` or
+/// `raise exc from cause`. Returns 1 if a bare `raise` re-raised the exception being handled and
+/// 0 otherwise. The exception is set in both cases.
+fn raise_varargs(sp: *const *mut PyObject, n: u64) -> i64 {
+    unsafe {
+        let n = n as usize;
+        if n == 0 {
+            let mut exc = std::ptr::null_mut();
+            let mut val = std::ptr::null_mut();
+            let mut tb = std::ptr::null_mut();
+            PyErr_GetExcInfo(&mut exc, &mut val, &mut tb);
+            if exc.is_null() || exc == Py_None() {
+                Py_XDECREF(exc);
+                Py_XDECREF(val);
+                Py_XDECREF(tb);
+                PyErr_SetString(
+                    PyExc_RuntimeError,
+                    c"No active exception to reraise".as_ptr(),
+                );
+                return 0;
+            }
+            PyErr_Restore(exc, val, tb);
+            return 1;
+        }
+        let exc = stack_item(sp, n, 0);
+        let cause = if n == 2 {
+            stack_item(sp, n, 1)
+        } else {
+            std::ptr::null_mut()
+        };
+        if PyExceptionClass_Check(exc) == 0 && PyExceptionInstance_Check(exc) == 0 {
+            Py_DECREF(exc);
+            Py_XDECREF(cause);
+            PyErr_SetString(
+                PyExc_TypeError,
+                c"exceptions must derive from BaseException".as_ptr(),
+            );
+            return 0;
+        }
+        let value = exception_instance(exc);
+        if value.is_null() {
+            Py_XDECREF(cause);
+            return 0;
+        }
+        if !cause.is_null() {
+            let fixed_cause = if cause == Py_None() {
+                Py_DECREF(cause);
+                std::ptr::null_mut()
+            } else if PyExceptionClass_Check(cause) != 0 || PyExceptionInstance_Check(cause) != 0 {
+                let fixed_cause = exception_instance(cause);
+                if fixed_cause.is_null() {
+                    Py_DECREF(value);
+                    return 0;
+                }
+                fixed_cause
+            } else {
+                Py_DECREF(cause);
+                Py_DECREF(value);
+                PyErr_SetString(
+                    PyExc_TypeError,
+                    c"exception causes must derive from BaseException".as_ptr(),
+                );
+                return 0;
+            };
+            PyException_SetCause(value, fixed_cause);
+        }
+        PyErr_SetObject(Py_TYPE(value) as *mut PyObject, value);
+        Py_DECREF(value);
+        0
+    }
+}
+
+/// Looks up a special method on the type of `obj` and binds it like `_PyObject_LookupSpecial`,
+/// raising AttributeError if it is missing. Returns a new reference, or NULL with an exception
+/// set.
+unsafe fn lookup_special(obj: *mut PyObject, name: &Identifier) -> *mut PyObject {
+    let name = name.get();
+    if name.is_null() {
+        return name;
+    }
+    let mut res = _PyType_Lookup(Py_TYPE(obj), name);
+    if res.is_null() {
+        if PyErr_Occurred().is_null() {
+            PyErr_SetObject(PyExc_AttributeError, name);
+        }
+    } else {
+        match (*Py_TYPE(res)).tp_descr_get {
+            Some(f) => res = f(res, obj, Py_TYPE(obj) as *mut PyObject),
+            None => Py_INCREF(res),
+        }
+    }
+    res
+}
+
+/// `SETUP_WITH` except for the block: replaces the context manager at `sp[0]` with its bound
+/// `__exit__` and returns the result of `__enter__`, or NULL with an exception set. `sp[0]` is
+/// a valid reference in either case.
+fn setup_with(sp: *mut *mut PyObject) -> *mut PyObject {
+    unsafe {
+        let mgr = *sp;
+        let enter = lookup_special(mgr, &ID_ENTER);
+        if enter.is_null() {
+            return std::ptr::null_mut();
+        }
+        let exit = lookup_special(mgr, &ID_EXIT);
+        if exit.is_null() {
+            Py_DECREF(enter);
+            return std::ptr::null_mut();
+        }
+        *sp = exit;
+        Py_DECREF(mgr);
+        let res = PyObject_CallNoArgs(enter);
+        Py_DECREF(enter);
+        res
+    }
+}
+
+/// `WITH_EXCEPT_START`: calls `__exit__`, which is below the six values of the handler, with the
+/// exception at the top. Returns a new reference or NULL with an exception set.
+fn with_except_start(sp: *const *mut PyObject, _arg: u64) -> *mut PyObject {
+    unsafe {
+        let argv = [std::ptr::null_mut(), *sp, *sp.add(1), *sp.add(2)];
+        PyObject_VectorcallDict(
+            *sp.add(6),
+            argv.as_ptr().add(1),
+            3 | PY_VECTORCALL_ARGUMENTS_OFFSET,
+            std::ptr::null_mut(),
+        )
+    }
+}
+
+// `f_state` of `PyFrameObject` in CPython 3.10
+const FRAME_SUSPENDED: i8 = -1;
+const FRAME_EXECUTING: i8 = 0;
+const FRAME_RETURNED: i8 = 1;
+const FRAME_RAISED: i8 = 3;
+
+/// Starts or resumes a generator frame: moves the frame's value stack, which holds the value
+/// sent to the generator on top, to `sp`. The block stack is tracked by the compiled code, so
+/// the frame's one is emptied.
+fn resume_frame(frame: *mut PyFrameObject, sp: *mut *mut PyObject) {
+    unsafe {
+        let depth = (*frame).f_stackdepth as usize;
+        for k in 0..depth {
+            *sp.add(depth - 1 - k) = *(*frame).f_valuestack.add(k);
+        }
+        (*frame).f_stackdepth = -1;
+        (*frame).f_iblock = 0;
+        (*frame).f_state = FRAME_EXECUTING;
+    }
+}
+
+/// Suspends a generator frame at the `index`-th instruction: moves the `depth` values below
+/// `sp[0]` to the frame's value stack and returns `sp[0]`, the value to yield.
+fn suspend_frame(
+    frame: *mut PyFrameObject,
+    sp: *const *mut PyObject,
+    depth: u32,
+    index: u32,
+) -> *mut PyObject {
+    unsafe {
+        for k in 0..depth as usize {
+            *(*frame).f_valuestack.add(k) = *sp.add(depth as usize - k);
+        }
+        (*frame).f_stackdepth = depth as c_int;
+        (*frame).f_lasti = index as c_int;
+        (*frame).f_state = FRAME_SUSPENDED;
+        *sp
+    }
+}
+
+/// Leaves a frame with `sp[0]` as the return value, releasing the `n` values below it which are
+/// still on the value stack.
+fn return_value(sp: *const *mut PyObject, n: u64) -> *mut PyObject {
+    unsafe {
+        for k in 1..=n as usize {
+            Py_XDECREF(*sp.add(k));
+        }
+        *sp
+    }
+}
+
+/// Marks a generator frame as finished by returning or raising.
+fn finish_frame(frame: *mut PyFrameObject, state: u32) {
+    unsafe {
+        (*frame).f_stackdepth = 0;
+        (*frame).f_state = state as i8;
+    }
+}
+
+/// `YIELD_FROM`: sends `sp[0]` to the iterator at `sp[1]`. Returns 1 with the value to yield
+/// in `sp[0]`, 0 when the iterator has returned with the result replacing it in `sp[1]`, or -1
+/// with an exception set. `sp[0]` is consumed unless 1 is returned.
+fn yield_from(sp: *mut *mut PyObject) -> i64 {
+    unsafe {
+        let (value, receiver) = (*sp, *sp.add(1));
+        let mut result = std::ptr::null_mut();
+        let status = PyIter_Send(receiver, value, &mut result);
+        Py_DECREF(value);
+        *sp = std::ptr::null_mut();
+        match status {
+            1 => *sp = result,
+            0 => {
+                Py_DECREF(receiver);
+                *sp.add(1) = result;
+            }
+            _ => {}
+        }
+        status as i64
+    }
+}
+
+/// `GET_YIELD_FROM_ITER`: returns the iterator `yield from` delegates to, or NULL with an
+/// exception set. Consumes the reference to `iterable`.
+fn get_yield_from_iter(iterable: *mut PyObject, co_flags: u32) -> *mut PyObject {
+    unsafe {
+        if PyCoro_CheckExact(iterable) != 0 {
+            if co_flags as c_int & (CO_COROUTINE | CO_ITERABLE_COROUTINE) == 0 {
+                Py_DECREF(iterable);
+                PyErr_SetString(
+                    PyExc_TypeError,
+                    c"cannot 'yield from' a coroutine object in a non-coroutine generator".as_ptr(),
+                );
+                return std::ptr::null_mut();
+            }
+            iterable
+        } else if PyGen_CheckExact(iterable) != 0 {
+            iterable
+        } else {
+            get_iter(iterable)
+        }
+    }
+}
+
+/// Whether `o` is a coroutine, or a generator made awaitable by `types.coroutine`.
+unsafe fn is_coroutine(o: *mut PyObject) -> bool {
+    PyCoro_CheckExact(o) != 0
+        || (PyGen_CheckExact(o) != 0
+            && (*((*(o as *mut PyGenObject)).gi_code as *mut PyCodeObject)).co_flags
+                & CO_ITERABLE_COROUTINE
+                != 0)
+}
+
+/// `GET_AWAITABLE`: returns the iterator of an awaitable like `_PyCoro_GetAwaitableIter`, or
+/// NULL with an exception set. Consumes the reference to `awaitable`.
+fn get_awaitable(awaitable: *mut PyObject) -> *mut PyObject {
+    unsafe {
+        let ty = Py_TYPE(awaitable);
+        let iter = if is_coroutine(awaitable) {
+            Py_INCREF(awaitable);
+            awaitable
+        } else {
+            match (*ty).tp_as_async.as_ref().and_then(|a| a.am_await) {
+                Some(am_await) => {
+                    let iter = am_await(awaitable);
+                    if !iter.is_null() && is_coroutine(iter) {
+                        Py_DECREF(iter);
+                        PyErr_SetString(
+                            PyExc_TypeError,
+                            c"__await__() returned a coroutine".as_ptr(),
+                        );
+                        std::ptr::null_mut()
+                    } else if !iter.is_null() && PyIter_Check(iter) == 0 {
+                        PyErr_Format(
+                            PyExc_TypeError,
+                            c"__await__() returned non-iterator of type '%.100s'".as_ptr(),
+                            (*Py_TYPE(iter)).tp_name,
+                        );
+                        Py_DECREF(iter);
+                        std::ptr::null_mut()
+                    } else {
+                        iter
+                    }
+                }
+                None => {
+                    PyErr_Format(
+                        PyExc_TypeError,
+                        c"object %.100s can't be used in 'await' expression".as_ptr(),
+                        (*ty).tp_name,
+                    );
+                    std::ptr::null_mut()
+                }
+            }
+        };
+        Py_DECREF(awaitable);
+        if !iter.is_null() && PyCoro_CheckExact(iter) != 0 {
+            let awaiting = PyObject_GetAttrString(iter, c"cr_await".as_ptr());
+            if awaiting.is_null() {
+                Py_DECREF(iter);
+                return std::ptr::null_mut();
+            }
+            let awaited = awaiting != Py_None();
+            Py_DECREF(awaiting);
+            if awaited {
+                Py_DECREF(iter);
+                PyErr_SetString(
+                    PyExc_RuntimeError,
+                    c"coroutine is being awaited already".as_ptr(),
+                );
+                return std::ptr::null_mut();
+            }
+        }
+        iter
+    }
+}
+
+/// Returns an iterator of `iterable`, or NULL with an exception set. Consumes the reference to
+/// `iterable`.
+fn get_iter(iterable: *mut PyObject) -> *mut PyObject {
+    unsafe {
+        let iter = PyObject_GetIter(iterable);
+        Py_DECREF(iterable);
+        iter
+    }
+}
+
+/// `FOR_ITER`: stores the next item of the iterator at `sp[1]` to `sp[0]` and returns 1.
+/// Returns 0 when the iterator is exhausted or -1 with an exception set.
+fn for_iter(sp: *mut *mut PyObject) -> i64 {
+    unsafe {
+        let iter = *sp.add(1);
+        let next = match (*Py_TYPE(iter)).tp_iternext {
+            Some(iternext) => iternext(iter),
+            None => {
+                PyErr_Format(
+                    PyExc_TypeError,
+                    c"'%.200s' object is not an iterator".as_ptr(),
+                    (*Py_TYPE(iter)).tp_name,
+                );
+                return -1;
+            }
+        };
+        if !next.is_null() {
+            *sp = next;
+            return 1;
+        }
+        if !PyErr_Occurred().is_null() {
+            if PyErr_ExceptionMatches(PyExc_StopIteration) == 0 {
+                return -1;
+            }
+            PyErr_Clear();
+        }
+        0
+    }
+}
+
+/// Leaves compiled code and resumes the interpreter at the `index`-th instruction with `depth`
+/// values of the native value stack, whose bottom is just below `rbp`. The values are moved to
+/// the frame's value stack.
+fn deopt(
+    frame: *mut PyFrameObject,
+    index: u32,
+    depth: u32,
+    rbp: *const *mut PyObject,
+) -> *mut PyObject {
+    info!("deopt index:{:?} depth:{:?}", index, depth);
+    unsafe {
+        for k in 0..depth as usize {
+            *(*frame).f_valuestack.add(k) = *rbp.sub(k + 1);
+        }
+        (*frame).f_lasti = index as c_int - 1;
+        (*frame).f_stackdepth = depth as c_int;
+        match ORIGINAL_FRAME {
+            Some(original) => original(PyThreadState_Get(), frame, 0),
+            None => panic!("original frame not found"),
+        }
+    }
+}
+
+pub static mut ORIGINAL_FRAME: Option<
+    extern "C" fn(state: *mut PyThreadState, frame: *mut PyFrameObject, c: i32) -> *mut PyObject,
+> = None;
+
+pub extern "C" fn eval(
+    state: *mut PyThreadState,
+    frame: *mut PyFrameObject,
+    c: i32,
+) -> *mut PyObject {
+    info!("eval()");
+
+    let jit_result = compile_and_exec_jit_code(state, frame, c);
+
+    match jit_result {
+        Some(result) => {
+            info!("jit result: {:?}", result);
+            result
+        }
+        None => unsafe {
+            if let Some(original) = ORIGINAL_FRAME {
+                original(state, frame, c)
+            } else {
+                panic!("original frame not found");
+            }
+        },
+    }
+}
+
+const ATTR_CACHE_SIZE: usize = 4;
+
+/// Where an attribute is found on instances of one type, valid while the type's version tag is
+/// unchanged.
+#[derive(Clone, Copy, Debug)]
+enum AttrKind {
+    /// A `__slots__` member stored at this offset in the object.
+    Slot(isize),
+    /// In the instance dict, or else this attribute of the class when it is not NULL. The class
+    /// attribute is not a descriptor.
+    InstanceDict(*mut PyObject),
+    /// A class attribute which is not a descriptor on a type without instance dicts.
+    ClassAttr(*mut PyObject),
+    /// A function of the class, for `LoadMethod`. It is shadowed by an entry of the instance dict.
+    Method(*mut PyObject),
+    /// The attribute found by `super(class, obj)` on the MRO of the type after `class`, before
+    /// binding it to `obj`.
+    Super {
+        class: *mut PyObject,
+        descr: *mut PyObject,
+    },
+}
+
+impl AttrKind {
+    /// The objects which a cache entry of this kind keeps alive
+    fn objects(&self) -> [*mut PyObject; 2] {
+        let null = std::ptr::null_mut();
+        match *self {
+            AttrKind::Slot(_) => [null, null],
+            AttrKind::InstanceDict(o) | AttrKind::ClassAttr(o) | AttrKind::Method(o) => [o, null],
+            AttrKind::Super { class, descr } => [class, descr],
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct AttrCacheEntry {
+    ty: *mut PyTypeObject,
+    version: c_uint,
+    kind: AttrKind,
+}
+
+/// The inline cache of one attribute access. Holds up to `ATTR_CACHE_SIZE` types and replaces
+/// the oldest entry when another type is seen.
+struct AttrCache {
+    /// The attribute name, which the cache keeps alive
+    name: *mut PyObject,
+    entries: [Option<AttrCacheEntry>; ATTR_CACHE_SIZE],
+    next: usize,
+}
+
+/// Attribute caches of one code object by (instruction index, opcode). Frames are compiled on
+/// every call, so the caches are kept in `co_extra` of the code object, which frees them with it.
+type AttrCaches = HashMap<(usize, u32), Box<AttrCache>>;
+
+/// The index of `co_extra` which holds `AttrCaches`
+static mut ATTR_CACHES_INDEX: Option<Py_ssize_t> = None;
+
+unsafe extern "C" fn free_attr_caches(caches: *mut c_void) {
+    drop(Box::from_raw(caches as *mut AttrCaches));
+}
+
+/// Returns the attribute caches of a code object, or None if `co_extra` is not available.
+unsafe fn attr_caches(code: *mut PyObject) -> Option<&'static mut AttrCaches> {
+    let index = match ATTR_CACHES_INDEX {
+        Some(index) => index,
+        None => {
+            let index = _PyEval_RequestCodeExtraIndex(free_attr_caches);
+            if index < 0 {
+                return None;
+            }
+            ATTR_CACHES_INDEX = Some(index as Py_ssize_t);
+            index as Py_ssize_t
+        }
+    };
+    let mut caches = std::ptr::null_mut();
+    if _PyCode_GetExtra(code, index, &caches) < 0 {
+        PyErr_Clear();
+        return None;
+    }
+    if caches.is_null() {
+        caches = Box::into_raw(Box::<AttrCaches>::default()) as *mut c_void;
+        if _PyCode_SetExtra(code, index, caches) < 0 {
+            PyErr_Clear();
+            free_attr_caches(caches);
+            return None;
+        }
+    }
+    Some(&mut *(caches as *mut AttrCaches))
+}
+
+impl AttrCache {
+    fn new(name: *mut PyObject) -> AttrCache {
+        unsafe { Py_INCREF(name) };
+        AttrCache {
+            name,
+            entries: [None; ATTR_CACHE_SIZE],
+            next: 0,
+        }
+    }
+
+    fn lookup(&self, ty: *mut PyTypeObject) -> Option<AttrKind> {
+        let version = valid_version_tag(ty)?;
+        self.entries
+            .iter()
+            .flatten()
+            .find(|e| e.ty == ty && e.version == version)
+            .map(|e| e.kind)
+    }
+
+    fn insert(&mut self, ty: *mut PyTypeObject, kind: AttrKind) {
+        let version = match valid_version_tag(ty) {
+            Some(v) => v,
+            None => return,
+        };
+        let entry = AttrCacheEntry { ty, version, kind };
+        let i = match self
+            .entries
+            .iter()
+            .position(|e| matches!(e, Some(e) if e.ty == ty))
+        {
+            Some(i) => i,
+            None => {
+                let i = self.next;
+                self.next = (self.next + 1) % ATTR_CACHE_SIZE;
+                i
+            }
+        };
+        for o in kind.objects() {
+            unsafe { Py_XINCREF(o) };
+        }
+        if let Some(old) = self.entries[i].replace(entry) {
+            for o in old.kind.objects() {
+                unsafe { Py_XDECREF(o) };
+            }
+        }
+    }
+}
+
+impl Drop for AttrCache {
+    fn drop(&mut self) {
+        for entry in self.entries.iter().flatten() {
+            for o in entry.kind.objects() {
+                unsafe { Py_XDECREF(o) };
+            }
+        }
+        unsafe { Py_DECREF(self.name) };
+    }
+}
+
+/// Whether attributes of instances of `ty` are read by `PyObject_GenericGetAttr`.
+unsafe fn has_generic_getattr(ty: *mut PyTypeObject) -> bool {
+    (*ty)
+        .tp_getattro
+        .is_some_and(|f| std::ptr::fn_addr_eq(f, PyObject_GenericGetAttr as getattrofunc))
+}
+
+/// Whether attributes of instances of `ty` are assigned by `PyObject_GenericSetAttr`.
+unsafe fn has_generic_setattr(ty: *mut PyTypeObject) -> bool {
+    (*ty)
+        .tp_setattro
+        .is_some_and(|f| std::ptr::fn_addr_eq(f, PyObject_GenericSetAttr as setattrofunc))
+}
+
+fn valid_version_tag(ty: *mut PyTypeObject) -> Option<c_uint> {
+    unsafe {
+        if (*ty).tp_flags & Py_TPFLAGS_VALID_VERSION_TAG == 0 {
+            return None;
+        }
+        Some((*ty).tp_version_tag)
+    }
+}
+
+/// Returns the member of a `__slots__` descriptor if it holds an object.
+fn slot_member(descr: *mut PyObject, writable: bool) -> Option<*mut PyMemberDef> {
+    unsafe {
+        if Py_TYPE(descr) != std::ptr::addr_of_mut!(PyMemberDescr_Type) {
+            return None;
+        }
+        let member = (*(descr as *mut PyMemberDescrObject)).d_member as *mut PyMemberDef;
+        if (*member).type_code != T_OBJECT_EX || (writable && (*member).flags & READONLY != 0) {
+            return None;
+        }
+        Some(member)
+    }
+}
+
+/// Finds how `name` is read from instances of `ty`, following `PyObject_GenericGetAttr`.
+fn attr_kind(ty: *mut PyTypeObject, name: *mut PyObject, method: bool) -> Option<AttrKind> {
+    unsafe {
+        if !has_generic_getattr(ty) {
+            return None;
+        }
+        // This also assigns a version tag to the type.
+        let descr = _PyType_Lookup(ty, name);
+        if descr.is_null() {
+            return if (*ty).tp_dictoffset != 0 && !method {
+                Some(AttrKind::InstanceDict(descr))
+            } else {
+                None
+            };
+        }
+        let descr_ty = Py_TYPE(descr);
+        if method && (*descr_ty).tp_flags & Py_TPFLAGS_METHOD_DESCRIPTOR != 0 {
+            return Some(AttrKind::Method(descr));
+        }
+        if method {
+            return None;
+        }
+        if let Some(member) = slot_member(descr, false) {
+            return Some(AttrKind::Slot((*member).offset));
+        }
+        if (*descr_ty).tp_descr_get.is_some() {
+            return None;
+        }
+        if (*ty).tp_dictoffset != 0 {
+            Some(AttrKind::InstanceDict(descr))
+        } else {
+            Some(AttrKind::ClassAttr(descr))
+        }
+    }
+}
+
+/// Finds how `name` is assigned or deleted on instances of `ty`, following
+/// `PyObject_GenericSetAttr`.
+fn store_attr_kind(ty: *mut PyTypeObject, name: *mut PyObject) -> Option<AttrKind> {
+    unsafe {
+        if !has_generic_setattr(ty) {
+            return None;
+        }
+        let descr = _PyType_Lookup(ty, name);
+        if !descr.is_null() {
+            if let Some(member) = slot_member(descr, true) {
+                return Some(AttrKind::Slot((*member).offset));
+            }
+            if (*Py_TYPE(descr)).tp_descr_set.is_some() {
+                return None;
+            }
+        }
+        if (*ty).tp_dictoffset != 0 {
+            Some(AttrKind::InstanceDict(std::ptr::null_mut()))
+        } else {
+            None
+        }
+    }
+}
+
+/// Returns the instance dict of `obj` or NULL if it has none yet.
+fn instance_dict(obj: *mut PyObject) -> *mut PyObject {
+    unsafe {
+        let dict_ptr = _PyObject_GetDictPtr(obj);
+        if dict_ptr.is_null() {
+            std::ptr::null_mut()
+        } else {
+            *dict_ptr
+        }
+    }
+}
+
+/// Computes `obj.name` using the cache of the instruction. Consumes the reference to `obj`.
+fn load_attr(obj: *mut PyObject, name: *mut PyObject, cache: *mut AttrCache) -> *mut PyObject {
+    unsafe {
+        let ty = Py_TYPE(obj);
+        let value = match (*cache).lookup(ty) {
+            Some(AttrKind::Slot(offset)) => {
+                *((obj as *mut u8).offset(offset) as *mut *mut PyObject)
+            }
+            Some(AttrKind::InstanceDict(class_attr)) => {
+                let dict = instance_dict(obj);
+                let value = if dict.is_null() {
+                    std::ptr::null_mut()
+                } else {
+                    PyDict_GetItem(dict, name)
+                };
+                if value.is_null() {
+                    class_attr
+                } else {
+                    value
+                }
+            }
+            Some(AttrKind::ClassAttr(class_attr)) => class_attr,
+            _ => std::ptr::null_mut(),
+        };
+        let value = if value.is_null() {
+            let value = PyObject_GetAttr(obj, name);
+            if let Some(kind) = attr_kind(ty, name, false) {
+                (*cache).insert(ty, kind);
+            }
+            value
+        } else {
+            Py_INCREF(value);
+            value
+        };
+        Py_DECREF(obj);
+        value
+    }
+}
+
+/// Assigns `obj.name = value`, or deletes `obj.name` if `value` is NULL, using the cache of the
+/// instruction. Consumes the references to `obj` and `value`. Returns 0, or -1 with an exception
+/// set.
+fn store_attr(
+    obj: *mut PyObject,
+    value: *mut PyObject,
+    name: *mut PyObject,
+    cache: *mut AttrCache,
+) -> i64 {
+    unsafe {
+        let ty = Py_TYPE(obj);
+        let done = match (*cache).lookup(ty) {
+            Some(AttrKind::Slot(offset)) => {
+                let slot = (obj as *mut u8).offset(offset) as *mut *mut PyObject;
+                let old = *slot;
+                if value.is_null() && old.is_null() {
+                    // Let the generic path raise AttributeError.
+                    None
+                } else {
+                    *slot = value;
+                    Py_XDECREF(old);
+                    Some(0)
+                }
+            }
+            Some(AttrKind::InstanceDict(_)) => {
+                let dict = instance_dict(obj);
+                if dict.is_null() {
+                    None
+                } else if !value.is_null() {
+                    let r = PyDict_SetItem(dict, name, value);
+                    Py_DECREF(value);
+                    Some(r)
+                } else if !PyDict_GetItem(dict, name).is_null() {
+                    Some(PyDict_DelItem(dict, name))
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        };
+        let r = match done {
+            Some(r) => r,
+            None => {
+                let r = PyObject_SetAttr(obj, name, value);
+                Py_XDECREF(value);
+                if let Some(kind) = store_attr_kind(ty, name) {
+                    (*cache).insert(ty, kind);
+                }
+                r
+            }
+        };
+        Py_DECREF(obj);
+        r as i64
+    }
+}
+
+/// Looks up the method `name` of the object in `slots[1]` using the cache of the instruction.
+/// Sets `slots[1]` to the function and `slots[0]` to the object like `LOAD_METHOD` if the
+/// attribute is a method, or otherwise `slots[1]` to NULL and `slots[0]` to the attribute.
+/// Returns 0, or -1 with an exception set and both slots NULL.
+fn load_method(slots: *mut *mut PyObject, name: *mut PyObject, cache: *mut AttrCache) -> i64 {
+    unsafe {
+        let obj = *slots.add(1);
+        let ty = Py_TYPE(obj);
+        let mut kind = (*cache).lookup(ty);
+        if kind.is_none() {
+            kind = attr_kind(ty, name, true);
+            if let Some(kind) = kind {
+                (*cache).insert(ty, kind);
+            }
+        }
+        if let Some(AttrKind::Method(func)) = kind {
+            let dict = instance_dict(obj);
+            if dict.is_null() || PyDict_GetItem(dict, name).is_null() {
+                Py_INCREF(func);
+                *slots.add(1) = func;
+                *slots = obj;
+                return 0;
+            }
+        }
+        let attr = PyObject_GetAttr(obj, name);
+        Py_DECREF(obj);
+        *slots.add(1) = std::ptr::null_mut();
+        *slots = attr;
+        if attr.is_null() {
+            -1
+        } else {
+            0
+        }
+    }
+}
+
+/// Finds the class and the first argument of the frame for zero-argument `super()`, as borrowed
+/// references, following `super_init_without_args`.
+unsafe fn super_args(frame: *mut PyFrameObject) -> Option<(*mut PyObject, *mut PyObject)> {
+    let code = (*frame).f_code;
+    let slots = std::ptr::addr_of_mut!((*frame).f_localsplus) as *mut *mut PyObject;
+    let error = |message: &CStr| {
+        PyErr_SetString(PyExc_RuntimeError, message.as_ptr());
+        None
+    };
+    if (*code).co_argcount == 0 {
+        return error(c"super(): no arguments");
+    }
+    let n_cells = PyTuple_Size((*code).co_cellvars) as usize;
+    let mut obj = *slots;
+    if obj.is_null() && !(*code).co_cell2arg.is_null() {
+        // The first argument is in a cell.
+        if let Some(i) = (0..n_cells).find(|i| *(*code).co_cell2arg.add(*i) == 0) {
+            let cell = *slots.add((*code).co_nlocals as usize + i);
+            obj = (*(cell as *mut PyCellObject)).ob_ref;
+        }
+    }
+    if obj.is_null() {
+        return error(c"super(): arg[0] deleted");
+    }
+    for i in 0..PyTuple_Size((*code).co_freevars) {
+        let name = PyTuple_GetItem((*code).co_freevars, i);
+        if PyUnicode_CompareWithASCIIString(name, c"__class__".as_ptr()) != 0 {
+            continue;
+        }
+        let cell = *slots.add((*code).co_nlocals as usize + n_cells + i as usize);
+        if cell.is_null() || Py_TYPE(cell) != std::ptr::addr_of_mut!(PyCell_Type) {
+            return error(c"super(): bad __class__ cell");
+        }
+        let class = (*(cell as *mut PyCellObject)).ob_ref;
+        if class.is_null() {
+            return error(c"super(): empty __class__ cell");
+        }
+        if PyType_Check(class) == 0 {
+            PyErr_Format(
+                PyExc_RuntimeError,
+                c"super(): __class__ is not a type (%s)".as_ptr(),
+                (*Py_TYPE(class)).tp_name,
+            );
+            return None;
+        }
+        return Some((class, obj));
+    }
+    error(c"super(): __class__ cell not found")
+}
+
+/// Finds `name` on the MRO of `start` after `class`, as `super` does. Returns a borrowed
+/// reference, or NULL, with an exception set only on an error, if no class after `class` has it.
+unsafe fn super_mro_lookup(
+    start: *mut PyTypeObject,
+    class: *mut PyObject,
+    name: *mut PyObject,
+) -> *mut PyObject {
+    let mro = (*start).tp_mro;
+    if mro.is_null() {
+        return mro;
+    }
+    let n = PyTuple_Size(mro);
+    let mut i = (0..n - 1)
+        .find(|i| PyTuple_GetItem(mro, *i) == class)
+        .unwrap_or(n - 1)
+        + 1;
+    Py_INCREF(mro);
+    let mut descr = std::ptr::null_mut();
+    while i < n {
+        let ty = PyTuple_GetItem(mro, i) as *mut PyTypeObject;
+        descr = PyDict_GetItemWithError((*ty).tp_dict, name);
+        if !descr.is_null() || !PyErr_Occurred().is_null() {
+            break;
+        }
+        i += 1;
+    }
+    Py_DECREF(mro);
+    descr
+}
+
+/// Gets `name` from `super(class, obj)` without creating the super object. If `method` is set
+/// and the attribute is a method which binds to `obj`, returns the function and true to call it
+/// with `obj` instead of a bound method. Returns None to leave the lookup to a super object.
+unsafe fn super_lookup(
+    class: *mut PyObject,
+    obj: *mut PyObject,
+    cache: *mut AttrCache,
+    method: bool,
+) -> Option<(*mut PyObject, bool)> {
+    let name = (*cache).name;
+    let start = if PyType_Check(obj) != 0
+        && PyType_IsSubtype(obj as *mut PyTypeObject, class as *mut PyTypeObject) != 0
+    {
+        obj as *mut PyTypeObject
+    } else if PyType_IsSubtype(Py_TYPE(obj), class as *mut PyTypeObject) != 0 {
+        Py_TYPE(obj)
+    } else {
+        return None;
+    };
+    // `super().__class__` is the class of the super object.
+    if PyUnicode_CompareWithASCIIString(name, c"__class__".as_ptr()) == 0 {
+        return None;
+    }
+    let descr = match (*cache).lookup(start) {
+        Some(AttrKind::Super { class: c, descr }) if c == class => descr,
+        _ => {
+            let descr = super_mro_lookup(start, class, name);
+            if descr.is_null() {
+                return if PyErr_Occurred().is_null() {
+                    None
+                } else {
+                    Some((descr, false))
+                };
+            }
+            // This assigns a version tag to the type if it has none.
+            _PyType_Lookup(start, name);
+            (*cache).insert(start, AttrKind::Super { class, descr });
+            descr
+        }
+    };
+    let descr_ty = Py_TYPE(descr);
+    if method
+        && has_generic_getattr(Py_TYPE(obj))
+        && (*descr_ty).tp_flags & Py_TPFLAGS_METHOD_DESCRIPTOR != 0
+    {
+        Py_INCREF(descr);
+        return Some((descr, true));
+    }
+    match (*descr_ty).tp_descr_get {
+        Some(get) => {
+            let instance = if obj == start as *mut PyObject {
+                std::ptr::null_mut()
+            } else {
+                obj
+            };
+            Some((get(descr, instance, start as *mut PyObject), false))
+        }
+        None => {
+            Py_INCREF(descr);
+            Some((descr, false))
+        }
+    }
+}
+
+/// Gets `name` from `global_super()`, or from `global_super(class, obj)` if `two_args` is set.
+unsafe fn super_object_attr(
+    global_super: *mut PyObject,
+    class: *mut PyObject,
+    obj: *mut PyObject,
+    name: *mut PyObject,
+    two_args: bool,
+) -> *mut PyObject {
+    let argv = [class, obj];
+    let nargs = if two_args { 2 } else { 0 };
+    let sup = PyObject_VectorcallDict(global_super, argv.as_ptr(), nargs, std::ptr::null_mut());
+    if sup.is_null() {
+        return sup;
+    }
+    let attr = PyObject_GetAttr(sup, name);
+    Py_DECREF(sup);
+    attr
+}
+
+/// Gets an attribute through zero-argument `super()`, with the `super` global and two NULLs as
+/// the top three values, where `sp` points to the top. The NULLs are replaced by the class and
+/// the object found in the frame. If `method` is set, the deepest two values become a function
+/// and the object or NULL and the attribute, as `LoadMethod` pushes them, and otherwise the
+/// deepest one the attribute. Returns 0, or -1 with an exception set.
+fn load_super_attr(
+    sp: *mut *mut PyObject,
+    method: u64,
+    cache: *mut AttrCache,
+    frame: *mut PyFrameObject,
+) -> i64 {
+    unsafe {
+        let global_super = *sp.add(2);
+        let is_super = global_super == std::ptr::addr_of_mut!(PySuper_Type) as *mut PyObject;
+        let mut two_args = false;
+        if is_super {
+            if let Some((class, obj)) = super_args(frame) {
+                Py_INCREF(class);
+                Py_INCREF(obj);
+                *sp.add(1) = class;
+                *sp = obj;
+                two_args = true;
+            }
+        }
+        let class = *sp.add(1);
+        let obj = *sp;
+        let method = method != 0;
+        let found = if !PyErr_Occurred().is_null() {
+            Some((std::ptr::null_mut(), false))
+        } else if is_super && PyType_Check(class) != 0 {
+            super_lookup(class, obj, cache, method)
+        } else {
+            None
+        };
+        let (attr, method_found) = found.unwrap_or_else(|| {
+            let attr = super_object_attr(global_super, class, obj, (*cache).name, two_args);
+            (attr, false)
+        });
+        Py_DECREF(global_super);
+        Py_XDECREF(class);
+        if method {
+            if method_found {
+                *sp.add(2) = attr;
+                *sp.add(1) = obj;
+            } else {
+                Py_XDECREF(obj);
+                *sp.add(2) = std::ptr::null_mut();
+                *sp.add(1) = attr;
+            }
+        } else {
+            Py_XDECREF(obj);
+            *sp.add(2) = attr;
+            *sp.add(1) = std::ptr::null_mut();
+        }
+        if attr.is_null() {
+            -1
+        } else {
+            0
+        }
+    }
+}
+
+const PY_VECTORCALL_ARGUMENTS_OFFSET: usize = 1 << (usize::BITS - 1);
+
+/// Calls a callable with `n` arguments on the native value stack, where `sp` points to the top
+/// value. For `Call` the values below the arguments are a function and `self` pushed by
+/// `LoadMethod`, or NULL and a callable. For `CallFunction` the value below is the callable.
+/// Consumes the references to all of them.
+fn call(sp: *const *mut PyObject, n: u64, method: u64) -> *mut PyObject {
+    unsafe {
+        let n = n as usize;
+        // The value stack grows downwards, so the i-th argument is at sp[n-1-i]. The first
+        // element is left for the callee, e.g. to prepend `self` of a bound method.
+        let mut argv = vec![std::ptr::null_mut(); n + 2];
+        for i in 0..n {
+            argv[i + 2] = *sp.add(n - 1 - i);
+        }
+        let (callable, args, nargs) = if method == 0 || (*sp.add(n + 1)).is_null() {
+            (*sp.add(n), 2, n)
+        } else {
+            argv[1] = *sp.add(n);
+            (*sp.add(n + 1), 1, n + 1)
+        };
+        // pyo3's inline PyObject_Vectorcall asserts on PY_VECTORCALL_ARGUMENTS_OFFSET for
+        // callables without vectorcall, so the exported function is called.
+        let r = PyObject_VectorcallDict(
+            callable,
+            argv.as_ptr().add(args),
+            nargs | PY_VECTORCALL_ARGUMENTS_OFFSET,
+            std::ptr::null_mut(),
+        );
+        Py_DECREF(callable);
+        for arg in &argv[args..] {
+            Py_DECREF(*arg);
+        }
+        r
+    }
+}
+
+/// Returns the i-th of `n` values on the native value stack, where `sp` points to the top value
+/// and the first value is the deepest.
+unsafe fn stack_item(sp: *const *mut PyObject, n: usize, i: usize) -> *mut PyObject {
+    *sp.add(n - 1 - i)
+}
+
+fn build_tuple(sp: *const *mut PyObject, n: u64) -> *mut PyObject {
+    unsafe {
+        let n = n as usize;
+        let tuple = PyTuple_New(n as isize);
+        for i in 0..n {
+            let item = stack_item(sp, n, i);
+            if tuple.is_null() {
+                Py_DECREF(item);
+            } else {
+                PyTuple_SET_ITEM(tuple, i as isize, item);
+            }
+        }
+        tuple
+    }
+}
+
+// The conversion selected by the argument of `FORMAT_VALUE` and `CONVERT_VALUE`, and the flag of
+// `FORMAT_VALUE` for a format spec on top of the value
+const FVC_MASK: u64 = 0x3;
+const FVC_STR: u64 = 0x1;
+const FVC_REPR: u64 = 0x2;
+const FVC_ASCII: u64 = 0x3;
+const FVS_HAVE_SPEC: u64 = 0x4;
+
+/// Applies `str()`, `repr()` or `ascii()` to `value` as selected by `conversion`, stealing the
+/// reference.
+fn convert(value: *mut PyObject, conversion: u64) -> *mut PyObject {
+    unsafe {
+        let r = match conversion & FVC_MASK {
+            FVC_STR => PyObject_Str(value),
+            FVC_REPR => PyObject_Repr(value),
+            FVC_ASCII => PyObject_ASCII(value),
+            _ => return value,
+        };
+        Py_DECREF(value);
+        r
+    }
+}
+
+/// Formats `value` with `spec`, or with an empty spec if it is NULL, stealing both references.
+/// A str is its own format and an int is formatted as by `str()`.
+fn format(value: *mut PyObject, spec: *mut PyObject) -> *mut PyObject {
+    unsafe {
+        if spec.is_null() && PyUnicode_CheckExact(value) != 0 {
+            return value;
+        }
+        let r = if spec.is_null() && PyLong_CheckExact(value) != 0 {
+            PyObject_Str(value)
+        } else {
+            PyObject_Format(value, spec)
+        };
+        Py_DECREF(value);
+        Py_XDECREF(spec);
+        r
+    }
+}
+
+/// `FORMAT_VALUE` of CPython 3.10, which converts and formats the value below the format spec
+/// if `flags` has one.
+fn format_value(sp: *const *mut PyObject, flags: u64) -> *mut PyObject {
+    unsafe {
+        let (value, spec) = if flags & FVS_HAVE_SPEC != 0 {
+            (*sp.add(1), *sp)
+        } else {
+            (*sp, std::ptr::null_mut())
+        };
+        let value = convert(value, flags);
+        if value.is_null() {
+            Py_XDECREF(spec);
+            return value;
+        }
+        format(value, spec)
+    }
+}
+
+/// Concatenates `n` strings.
+fn build_string(sp: *const *mut PyObject, n: u64) -> *mut PyObject {
+    unsafe {
+        let n = n as usize;
+        let items: Vec<*mut PyObject> = (0..n).map(|i| stack_item(sp, n, i)).collect();
+        let empty = PyUnicode_New(0, 0);
+        let r = if empty.is_null() {
+            empty
+        } else {
+            let r = _PyUnicode_JoinArray(empty, items.as_ptr(), n as isize);
+            Py_DECREF(empty);
+            r
+        };
+        for item in items {
+            Py_DECREF(item);
+        }
+        r
+    }
+}
+
+/// Calls `sys.displayhook` with `value` for an expression statement of the interactive prompt.
+fn intrinsic_print(_frame: *mut PyFrameObject, value: *mut PyObject) -> *mut PyObject {
+    unsafe {
+        let hook = PySys_GetObject(c"displayhook".as_ptr());
+        if hook.is_null() {
+            PyErr_SetString(PyExc_RuntimeError, c"lost sys.displayhook".as_ptr());
+            return std::ptr::null_mut();
+        }
+        let argv = [value];
+        PyObject_VectorcallDict(hook, argv.as_ptr(), 1, std::ptr::null_mut())
+    }
+}
+
+/// Copies the public names of the module `from` into `locals` for `from module import *`.
+unsafe fn import_all_from(locals: *mut PyObject, from: *mut PyObject) -> c_int {
+    let mut all = std::ptr::null_mut();
+    let attr = ID_ALL.get();
+    if attr.is_null() || _PyObject_LookupAttr(from, attr, &mut all) < 0 {
+        return -1;
+    }
+    let skip_leading_underscores = all.is_null();
+    if all.is_null() {
+        let mut dict = std::ptr::null_mut();
+        let attr = ID_DICT.get();
+        if attr.is_null() || _PyObject_LookupAttr(from, attr, &mut dict) < 0 {
+            return -1;
+        }
+        if dict.is_null() {
+            PyErr_SetString(
+                PyExc_ImportError,
+                c"from-import-* object has no __dict__ and no __all__".as_ptr(),
+            );
+            return -1;
+        }
+        all = PyMapping_Keys(dict);
+        Py_DECREF(dict);
+        if all.is_null() {
+            return -1;
+        }
+    }
+    let mut err = 0;
+    let mut pos = 0;
+    loop {
+        let name = PySequence_GetItem(all, pos);
+        pos += 1;
+        if name.is_null() {
+            if PyErr_ExceptionMatches(PyExc_IndexError) == 0 {
+                err = -1;
+            } else {
+                PyErr_Clear();
+            }
+            break;
+        }
+        if PyUnicode_Check(name) == 0 {
+            let modname = PyObject_GetAttrString(from, c"__name__".as_ptr());
+            if modname.is_null() {
+                // The AttributeError is raised.
+            } else if PyUnicode_Check(modname) == 0 {
+                PyErr_Format(
+                    PyExc_TypeError,
+                    c"module __name__ must be a string, not %.100s".as_ptr(),
+                    (*Py_TYPE(modname)).tp_name,
+                );
+            } else {
+                let (kind, attr) = if skip_leading_underscores {
+                    (c"Key", c"__dict__")
+                } else {
+                    (c"Item", c"__all__")
+                };
+                PyErr_Format(
+                    PyExc_TypeError,
+                    c"%s in %U.%s must be str, not %.100s".as_ptr(),
+                    kind.as_ptr(),
+                    modname,
+                    attr.as_ptr(),
+                    (*Py_TYPE(name)).tp_name,
+                );
+            }
+            Py_XDECREF(modname);
+            Py_DECREF(name);
+            err = -1;
+            break;
+        }
+        if skip_leading_underscores
+            && PyUnicode_GetLength(name) > 0
+            && PyUnicode_ReadChar(name, 0) == '_' as u32
+        {
+            Py_DECREF(name);
+            continue;
+        }
+        let value = PyObject_GetAttr(from, name);
+        err = if value.is_null() {
+            -1
+        } else if Py_TYPE(locals) == std::ptr::addr_of_mut!(PyDict_Type) {
+            PyDict_SetItem(locals, name, value)
+        } else {
+            PyObject_SetItem(locals, name, value)
+        };
+        Py_DECREF(name);
+        Py_XDECREF(value);
+        if err != 0 {
+            break;
+        }
+    }
+    Py_DECREF(all);
+    err
+}
+
+/// Imports all public names of the module `from` into the frame's locals. Returns None.
+fn intrinsic_import_star(frame: *mut PyFrameObject, from: *mut PyObject) -> *mut PyObject {
+    unsafe {
+        if PyFrame_FastToLocalsWithError(frame) < 0 {
+            return std::ptr::null_mut();
+        }
+        let locals = (*frame).f_locals;
+        if locals.is_null() {
+            PyErr_SetString(
+                PyExc_SystemError,
+                c"no locals found during 'import *'".as_ptr(),
+            );
+            return std::ptr::null_mut();
+        }
+        let err = import_all_from(locals, from);
+        PyFrame_LocalsToFast(frame, 0);
+        if err < 0 {
+            return std::ptr::null_mut();
+        }
+        Py_INCREF(Py_None());
+        Py_None()
+    }
+}
+
+fn intrinsic_unary_positive(_frame: *mut PyFrameObject, value: *mut PyObject) -> *mut PyObject {
+    unsafe { PyNumber_Positive(value) }
+}
+
+fn intrinsic_list_to_tuple(_frame: *mut PyFrameObject, value: *mut PyObject) -> *mut PyObject {
+    unsafe { PyList_AsTuple(value) }
+}
+
+type Intrinsic1 = fn(*mut PyFrameObject, *mut PyObject) -> *mut PyObject;
+
+/// The functions of `CALL_INTRINSIC_1` by argument, for the instructions of CPython 3.10 which
+/// `decode` and the compiler translate to it. The others have no 3.10 equivalent.
+const INTRINSICS_1: [Option<Intrinsic1>; 7] = [
+    None,
+    Some(intrinsic_print),
+    Some(intrinsic_import_star),
+    // INTRINSIC_STOPITERATION_ERROR and INTRINSIC_ASYNC_GEN_WRAP
+    None,
+    None,
+    Some(intrinsic_unary_positive),
+    Some(intrinsic_list_to_tuple),
+];
+
+/// Calls the intrinsic `i` with the value on top of the stack and the frame in `frame`.
+fn call_intrinsic_1(sp: *const *mut PyObject, i: u64, frame: *mut PyFrameObject) -> *mut PyObject {
+    unsafe {
+        let value = *sp;
+        let r = INTRINSICS_1[i as usize].unwrap()(frame, value);
+        Py_DECREF(value);
+        r
+    }
+}
+
+/// Pushes the length of the value on top of the stack.
+fn get_len(sp: *const *mut PyObject, _arg: u64) -> *mut PyObject {
+    unsafe {
+        let len = PyObject_Length(*sp);
+        if len < 0 {
+            return std::ptr::null_mut();
+        }
+        PyLong_FromSsize_t(len)
+    }
+}
+
+/// `MATCH_KEYS` of CPython 3.10: returns a tuple of the values of the mapping below the stack
+/// top for the keys on top, or None if one of the keys is missing.
+fn match_keys(sp: *const *mut PyObject, _arg: u64) -> *mut PyObject {
+    unsafe {
+        let keys = *sp;
+        let map = *sp.add(1);
+        let n = PyTuple_Size(keys);
+        if n == 0 {
+            return PyTuple_New(0);
+        }
+        // `get` with a default tells a missing key without raising, and without creating it in
+        // dict subclasses which define `__missing__`.
+        let get = PyObject_GetAttrString(map, c"get".as_ptr());
+        if get.is_null() {
+            return get;
+        }
+        let seen = PySet_New(std::ptr::null_mut());
+        let dummy = PyObject_CallNoArgs(std::ptr::addr_of_mut!(PyBaseObject_Type) as *mut PyObject);
+        let mut values = PyList_New(0);
+        if seen.is_null() || dummy.is_null() || values.is_null() {
+            Py_DECREF(get);
+            Py_XDECREF(seen);
+            Py_XDECREF(dummy);
+            Py_XDECREF(values);
+            return std::ptr::null_mut();
+        }
+        for i in 0..n {
+            let key = PyTuple_GetItem(keys, i);
+            if PySet_Contains(seen, key) != 0 || PySet_Add(seen, key) != 0 {
+                if PyErr_Occurred().is_null() {
+                    PyErr_Format(
+                        PyExc_ValueError,
+                        c"mapping pattern checks duplicate key (%R)".as_ptr(),
+                        key,
+                    );
+                }
+                Py_DECREF(values);
+                values = std::ptr::null_mut();
+                break;
+            }
+            let argv = [key, dummy];
+            let value = PyObject_VectorcallDict(get, argv.as_ptr(), 2, std::ptr::null_mut());
+            if value.is_null() {
+                Py_DECREF(values);
+                values = std::ptr::null_mut();
+                break;
+            }
+            if value == dummy {
+                Py_DECREF(value);
+                Py_DECREF(values);
+                values = Py_None();
+                Py_INCREF(values);
+                break;
+            }
+            let r = PyList_Append(values, value);
+            Py_DECREF(value);
+            if r < 0 {
+                Py_DECREF(values);
+                values = std::ptr::null_mut();
+                break;
+            }
+        }
+        if !values.is_null() && values != Py_None() {
+            let list = values;
+            values = PyList_AsTuple(list);
+            Py_DECREF(list);
+        }
+        Py_DECREF(get);
+        Py_DECREF(seen);
+        Py_DECREF(dummy);
+        values
+    }
+}
+
+/// `COPY_DICT_WITHOUT_KEYS` of CPython 3.10: returns a copy of the mapping below the stack top
+/// without the keys on top, which it pops.
+fn copy_dict_without_keys(sp: *const *mut PyObject, _arg: u64) -> *mut PyObject {
+    unsafe {
+        let keys = *sp;
+        let mut rest = PyDict_New();
+        if !rest.is_null() && PyDict_Update(rest, *sp.add(1)) < 0 {
+            Py_DECREF(rest);
+            rest = std::ptr::null_mut();
+        }
+        for i in 0..PyTuple_Size(keys) {
+            if !rest.is_null() && PyDict_DelItem(rest, PyTuple_GetItem(keys, i)) < 0 {
+                Py_DECREF(rest);
+                rest = std::ptr::null_mut();
+            }
+        }
+        Py_DECREF(keys);
+        rest
+    }
+}
+
+// `_Py_TPFLAGS_MATCH_SELF` of CPython 3.10, set on the builtins which a class pattern matches
+// with one positional sub-pattern as a whole
+const TPFLAGS_MATCH_SELF: c_ulong = 1 << 22;
+
+/// Gets the attribute `name` of `subject` for a class pattern. Returns NULL without an exception
+/// if it is missing.
+unsafe fn match_class_attr(
+    subject: *mut PyObject,
+    ty: *mut PyObject,
+    name: *mut PyObject,
+    seen: *mut PyObject,
+) -> *mut PyObject {
+    if PySet_Contains(seen, name) != 0 || PySet_Add(seen, name) != 0 {
+        if PyErr_Occurred().is_null() {
+            PyErr_Format(
+                PyExc_TypeError,
+                c"%s() got multiple sub-patterns for attribute %R".as_ptr(),
+                (*(ty as *mut PyTypeObject)).tp_name,
+                name,
+            );
+        }
+        return std::ptr::null_mut();
+    }
+    let attr = PyObject_GetAttr(subject, name);
+    if attr.is_null() && PyErr_ExceptionMatches(PyExc_AttributeError) != 0 {
+        PyErr_Clear();
+    }
+    attr
+}
+
+/// Returns a tuple of the attributes of `subject` matched by a class pattern of `ty` with `nargs`
+/// positional and the `kwargs` keyword sub-patterns. Returns NULL, with an exception set only on
+/// an error, if the pattern does not match.
+unsafe fn match_class_attrs(
+    subject: *mut PyObject,
+    ty: *mut PyObject,
+    nargs: isize,
+    kwargs: *mut PyObject,
+) -> *mut PyObject {
+    if PyType_Check(ty) == 0 {
+        PyErr_SetString(
+            PyExc_TypeError,
+            c"called match pattern must be a type".as_ptr(),
+        );
+        return std::ptr::null_mut();
+    }
+    if PyObject_IsInstance(subject, ty) <= 0 {
+        return std::ptr::null_mut();
+    }
+    let type_name = (*(ty as *mut PyTypeObject)).tp_name;
+    let mut match_self = false;
+    let mut match_args = std::ptr::null_mut();
+    if nargs > 0 {
+        match_args = PyObject_GetAttrString(ty, c"__match_args__".as_ptr());
+        if match_args.is_null() {
+            if PyErr_ExceptionMatches(PyExc_AttributeError) == 0 {
+                return std::ptr::null_mut();
+            }
+            // The flag counts only for types without `__match_args__`, so subclasses lose it by
+            // defining one.
+            PyErr_Clear();
+            match_self = (*(ty as *mut PyTypeObject)).tp_flags & TPFLAGS_MATCH_SELF != 0;
+        } else if PyTuple_CheckExact(match_args) == 0 {
+            PyErr_Format(
+                PyExc_TypeError,
+                c"%s.__match_args__ must be a tuple (got %s)".as_ptr(),
+                type_name,
+                (*Py_TYPE(match_args)).tp_name,
+            );
+            Py_DECREF(match_args);
+            return std::ptr::null_mut();
+        }
+        let allowed = if match_self {
+            1
+        } else if match_args.is_null() {
+            0
+        } else {
+            PyTuple_Size(match_args)
+        };
+        if allowed < nargs {
+            PyErr_Format(
+                PyExc_TypeError,
+                c"%s() accepts %zd positional sub-pattern%s (%zd given)".as_ptr(),
+                type_name,
+                allowed,
+                if allowed == 1 { c"" } else { c"s" }.as_ptr(),
+                nargs,
+            );
+            Py_XDECREF(match_args);
+            return std::ptr::null_mut();
+        }
+    }
+    // Each name is checked and looked up in turn, so a missing attribute before a name which is
+    // not a str is no match rather than an error.
+    let n_kwargs = PyTuple_Size(kwargs);
+    let attrs = PyTuple_New(nargs + n_kwargs);
+    let seen = PySet_New(std::ptr::null_mut());
+    let mut r = attrs;
+    if attrs.is_null() || seen.is_null() {
+        r = std::ptr::null_mut();
+    } else {
+        for i in 0..nargs + n_kwargs {
+            let attr = if i >= nargs {
+                match_class_attr(subject, ty, PyTuple_GetItem(kwargs, i - nargs), seen)
+            } else if match_self {
+                Py_INCREF(subject);
+                subject
+            } else {
+                let name = PyTuple_GetItem(match_args, i);
+                if PyUnicode_CheckExact(name) == 0 {
+                    PyErr_Format(
+                        PyExc_TypeError,
+                        c"__match_args__ elements must be strings (got %s)".as_ptr(),
+                        (*Py_TYPE(name)).tp_name,
+                    );
+                    std::ptr::null_mut()
+                } else {
+                    match_class_attr(subject, ty, name, seen)
+                }
+            };
+            if attr.is_null() {
+                r = std::ptr::null_mut();
+                break;
+            }
+            PyTuple_SET_ITEM(attrs, i, attr);
+        }
+    }
+    if r.is_null() {
+        Py_XDECREF(attrs);
+    }
+    Py_XDECREF(seen);
+    Py_XDECREF(match_args);
+    r
+}
+
+/// `MATCH_CLASS` of CPython 3.10: pops the keyword names on top and returns whether the subject
+/// two below matches the class between them, which it consumes. On a match the subject is
+/// replaced by the tuple of the matched attributes.
+fn match_class(sp: *mut *mut PyObject, nargs: u64) -> *mut PyObject {
+    unsafe {
+        let names = *sp;
+        let ty = *sp.add(1);
+        let subject = *sp.add(2);
+        let attrs = match_class_attrs(subject, ty, nargs as isize, names);
+        Py_DECREF(names);
+        Py_DECREF(ty);
+        if !attrs.is_null() {
+            Py_DECREF(subject);
+            *sp.add(2) = attrs;
+        } else if !PyErr_Occurred().is_null() {
+            return std::ptr::null_mut();
+        }
+        let r = if attrs.is_null() {
+            Py_False()
+        } else {
+            Py_True()
+        };
+        Py_INCREF(r);
+        r
+    }
+}
+
+fn build_list(sp: *const *mut PyObject, n: u64) -> *mut PyObject {
+    unsafe {
+        let n = n as usize;
+        let list = PyList_New(n as isize);
+        for i in 0..n {
+            let item = stack_item(sp, n, i);
+            if list.is_null() {
+                Py_DECREF(item);
+            } else {
+                PyList_SET_ITEM(list, i as isize, item);
+            }
+        }
+        list
+    }
+}
+
+fn build_set(sp: *const *mut PyObject, n: u64) -> *mut PyObject {
+    unsafe {
+        let n = n as usize;
+        let mut set = PySet_New(std::ptr::null_mut());
+        for i in 0..n {
+            let item = stack_item(sp, n, i);
+            if !set.is_null() && PySet_Add(set, item) < 0 {
+                Py_DECREF(set);
+                set = std::ptr::null_mut();
+            }
+            Py_DECREF(item);
+        }
+        set
+    }
+}
+
+/// Builds a dict from `n` pairs of a key and a value.
+fn build_map(sp: *const *mut PyObject, n: u64) -> *mut PyObject {
+    unsafe {
+        let n = n as usize;
+        let mut map = _PyDict_NewPresized(n as isize);
+        for i in 0..n {
+            let key = stack_item(sp, 2 * n, 2 * i);
+            let value = stack_item(sp, 2 * n, 2 * i + 1);
+            if !map.is_null() && PyDict_SetItem(map, key, value) < 0 {
+                Py_DECREF(map);
+                map = std::ptr::null_mut();
+            }
+            Py_DECREF(key);
+            Py_DECREF(value);
+        }
+        map
+    }
+}
+
+/// Builds a dict from `n` values and a tuple of their keys on top of them.
+fn build_const_key_map(sp: *const *mut PyObject, n: u64) -> *mut PyObject {
+    unsafe {
+        let n = n as usize;
+        let keys = *sp;
+        let mut map = if PyTuple_Check(keys) == 0 || PyTuple_Size(keys) != n as isize {
+            PyErr_SetString(
+                PyExc_SystemError,
+                c"bad BUILD_CONST_KEY_MAP keys argument".as_ptr(),
+            );
+            std::ptr::null_mut()
+        } else {
+            _PyDict_NewPresized(n as isize)
+        };
+        for i in 0..n {
+            let value = stack_item(sp.add(1), n, i);
+            if !map.is_null() && PyDict_SetItem(map, PyTuple_GetItem(keys, i as isize), value) < 0 {
+                Py_DECREF(map);
+                map = std::ptr::null_mut();
+            }
+            Py_DECREF(value);
+        }
+        Py_DECREF(keys);
+        map
+    }
+}
+
+/// `LIST_APPEND(i)`, `SET_ADD(i)`, `LIST_EXTEND(i)`, `SET_UPDATE(i)`, `DICT_UPDATE(i)` and
+/// `DICT_MERGE(i)` pop the top value into the container which is then the i-th value from the top.
+/// The functions below return 0, or -1 with an exception set, and consume the popped value.
+fn list_append(sp: *const *mut PyObject, i: u64) -> i64 {
+    unsafe {
+        let item = *sp;
+        let r = PyList_Append(*sp.add(i as usize), item);
+        Py_DECREF(item);
+        r as i64
+    }
+}
+
+fn set_add(sp: *const *mut PyObject, i: u64) -> i64 {
+    unsafe {
+        let item = *sp;
+        let r = PySet_Add(*sp.add(i as usize), item);
+        Py_DECREF(item);
+        r as i64
+    }
+}
+
+fn list_extend(sp: *const *mut PyObject, i: u64) -> i64 {
+    unsafe {
+        let iterable = *sp;
+        let none = _PyList_Extend(*sp.add(i as usize), iterable);
+        let r = if none.is_null() {
+            let ty = Py_TYPE(iterable);
+            if PyErr_ExceptionMatches(PyExc_TypeError) != 0
+                && (*ty).tp_iter.is_none()
+                && PySequence_Check(iterable) == 0
+            {
+                PyErr_Clear();
+                PyErr_Format(
+                    PyExc_TypeError,
+                    c"Value after * must be an iterable, not %.200s".as_ptr(),
+                    (*ty).tp_name,
+                );
+            }
+            -1
+        } else {
+            Py_DECREF(none);
+            0
+        };
+        Py_DECREF(iterable);
+        r
+    }
+}
+
+fn set_update(sp: *const *mut PyObject, i: u64) -> i64 {
+    unsafe {
+        let iterable = *sp;
+        let r = _PySet_Update(*sp.add(i as usize), iterable);
+        Py_DECREF(iterable);
+        r as i64
+    }
+}
+
+fn dict_update(sp: *const *mut PyObject, i: u64) -> i64 {
+    unsafe {
+        let update = *sp;
+        let r = PyDict_Update(*sp.add(i as usize), update);
+        if r < 0 && PyErr_ExceptionMatches(PyExc_AttributeError) != 0 {
+            PyErr_Format(
+                PyExc_TypeError,
+                c"'%.200s' object is not a mapping".as_ptr(),
+                (*Py_TYPE(update)).tp_name,
+            );
+        }
+        Py_DECREF(update);
+        r as i64
+    }
+}
+
+/// Like `dict_update` but raises TypeError for duplicate keys, which are keyword arguments of
+/// the function two values below the dict.
+fn dict_merge(sp: *const *mut PyObject, i: u64) -> i64 {
+    unsafe {
+        let update = *sp;
+        let func = *sp.add(i as usize + 2);
+        let r = _PyDict_MergeEx(*sp.add(i as usize), update, 2);
+        if r < 0 {
+            format_kwargs_error(func, update);
+        }
+        Py_DECREF(update);
+        r as i64
+    }
+}
+
+fn format_kwargs_error(func: *mut PyObject, kwargs: *mut PyObject) {
+    unsafe {
+        if PyErr_ExceptionMatches(PyExc_AttributeError) != 0 {
+            let func_str = _PyObject_FunctionStr(func);
+            if !func_str.is_null() {
+                PyErr_Format(
+                    PyExc_TypeError,
+                    c"%U argument after ** must be a mapping, not %.200s".as_ptr(),
+                    func_str,
+                    (*Py_TYPE(kwargs)).tp_name,
+                );
+                Py_DECREF(func_str);
+            }
+        } else if PyErr_ExceptionMatches(PyExc_KeyError) != 0 {
+            let mut exc = std::ptr::null_mut();
+            let mut val = std::ptr::null_mut();
+            let mut tb = std::ptr::null_mut();
+            PyErr_Fetch(&mut exc, &mut val, &mut tb);
+            if !val.is_null() && PyTuple_Check(val) != 0 && PyTuple_Size(val) == 1 {
+                let func_str = _PyObject_FunctionStr(func);
+                if !func_str.is_null() {
+                    PyErr_Format(
+                        PyExc_TypeError,
+                        c"%U got multiple values for keyword argument '%S'".as_ptr(),
+                        func_str,
+                        PyTuple_GetItem(val, 0),
+                    );
+                    Py_DECREF(func_str);
+                }
+                Py_XDECREF(exc);
+                Py_XDECREF(val);
+                Py_XDECREF(tb);
+            } else {
+                PyErr_Restore(exc, val, tb);
+            }
+        }
+    }
+}
+
+/// `MAP_ADD(i)` pops a key and a value and stores them in the dict which is then the i-th value
+/// from the top.
+fn map_add(sp: *const *mut PyObject, i: u64) -> i64 {
+    unsafe {
+        let value = *sp;
+        let key = *sp.add(1);
+        let r = PyDict_SetItem(*sp.add(i as usize + 1), key, value);
+        Py_DECREF(key);
+        Py_DECREF(value);
+        r as i64
+    }
+}
+
+/// Computes `container[key]`. Consumes the references to `container` and `key`.
+fn binary_subscr(container: *mut PyObject, key: *mut PyObject) -> *mut PyObject {
+    unsafe {
+        let r = PyObject_GetItem(container, key);
+        Py_DECREF(container);
+        Py_DECREF(key);
+        r
+    }
+}
+
+/// `binary_subscr` for an exact dict, which has no `__missing__`.
+fn dict_subscr(dict: *mut PyObject, key: *mut PyObject) -> *mut PyObject {
+    unsafe {
+        let r = PyDict_GetItemWithError(dict, key);
+        if r.is_null() {
+            if PyErr_Occurred().is_null() {
+                _PyErr_SetKeyError(key);
+            }
+        } else {
+            Py_INCREF(r);
+        }
+        Py_DECREF(dict);
+        Py_DECREF(key);
+        r
+    }
+}
+
+/// Assigns `container[key] = value`, or deletes `container[key]` if `value` is NULL. Consumes the
+/// references to all of them. Returns 0, or -1 with an exception set.
+fn store_subscr(value: *mut PyObject, container: *mut PyObject, key: *mut PyObject) -> i64 {
+    unsafe {
+        let r = if value.is_null() {
+            PyObject_DelItem(container, key)
+        } else if Py_TYPE(container) == std::ptr::addr_of_mut!(PyDict_Type) {
+            PyDict_SetItem(container, key, value)
+        } else {
+            PyObject_SetItem(container, key, value)
+        };
+        Py_XDECREF(value);
+        Py_DECREF(container);
+        Py_DECREF(key);
+        r as i64
+    }
+}
+
+/// Builds `slice(start, stop[, step])` from `n` values. Consumes the references to them.
+fn build_slice(sp: *const *mut PyObject, n: u64) -> *mut PyObject {
+    unsafe {
+        let n = n as usize;
+        let step = if n == 3 { *sp } else { std::ptr::null_mut() };
+        let start = stack_item(sp, n, 0);
+        let stop = stack_item(sp, n, 1);
+        let slice = PySlice_New(start, stop, step);
+        Py_DECREF(start);
+        Py_DECREF(stop);
+        Py_XDECREF(step);
+        slice
+    }
+}
+
+/// Unpacks `seq` into `out`, which receives the targets in assignment order. Without a starred
+/// target `n_after` is None and `n_before` is the number of targets. Otherwise the targets are
+/// `n_before` items, a list of the rest and `n_after` items. Consumes the reference to `seq` and
+/// returns 0, or -1 with an exception set and `out` cleared.
+fn unpack_iterable(
+    out: *mut *mut PyObject,
+    seq: *mut PyObject,
+    n_before: usize,
+    n_after: Option<usize>,
+) -> i64 {
+    unsafe {
+        let n_out = n_before + n_after.map_or(0, |n| n + 1);
+        for k in 0..n_out {
+            *out.add(k) = std::ptr::null_mut();
+        }
+        if n_after.is_none() {
+            let is_tuple = Py_TYPE(seq) == std::ptr::addr_of_mut!(PyTuple_Type);
+            let is_list = Py_TYPE(seq) == std::ptr::addr_of_mut!(PyList_Type);
+            if (is_tuple || is_list) && var_size(seq) == n_before as isize {
+                for k in 0..n_before {
+                    let item = if is_tuple {
+                        PyTuple_GetItem(seq, k as isize)
+                    } else {
+                        PyList_GetItem(seq, k as isize)
+                    };
+                    Py_INCREF(item);
+                    *out.add(k) = item;
+                }
+                Py_DECREF(seq);
+                return 0;
+            }
+        }
+
+        let r = unpack_iterator(out, seq, n_before, n_after);
+        if r < 0 {
+            for k in 0..n_out {
+                Py_XDECREF(*out.add(k));
+                *out.add(k) = std::ptr::null_mut();
+            }
+        }
+        Py_DECREF(seq);
+        r
+    }
+}
+
+/// Returns ob_size of a tuple or a list.
+unsafe fn var_size(seq: *mut PyObject) -> isize {
+    (*(seq as *mut PyVarObject)).ob_size
+}
+
+fn unpack_iterator(
+    out: *mut *mut PyObject,
+    seq: *mut PyObject,
+    n_before: usize,
+    n_after: Option<usize>,
+) -> i64 {
+    unsafe {
+        let it = PyObject_GetIter(seq);
+        if it.is_null() {
+            let ty = Py_TYPE(seq);
+            if PyErr_ExceptionMatches(PyExc_TypeError) != 0
+                && (*ty).tp_iter.is_none()
+                && PySequence_Check(seq) == 0
+            {
+                PyErr_Format(
+                    PyExc_TypeError,
+                    c"cannot unpack non-iterable %.200s object".as_ptr(),
+                    (*ty).tp_name,
+                );
+            }
+            return -1;
+        }
+        let r = (|| {
+            for k in 0..n_before {
+                let item = PyIter_Next(it);
+                if item.is_null() {
+                    if PyErr_Occurred().is_null() {
+                        match n_after {
+                            None => PyErr_Format(
+                                PyExc_ValueError,
+                                c"not enough values to unpack (expected %d, got %d)".as_ptr(),
+                                n_before as c_int,
+                                k as c_int,
+                            ),
+                            Some(n_after) => PyErr_Format(
+                                PyExc_ValueError,
+                                c"not enough values to unpack (expected at least %d, got %d)"
+                                    .as_ptr(),
+                                (n_before + n_after) as c_int,
+                                k as c_int,
+                            ),
+                        };
+                    }
+                    return -1;
+                }
+                *out.add(k) = item;
+            }
+            let n_after = match n_after {
+                Some(n) => n,
+                None => {
+                    let item = PyIter_Next(it);
+                    if item.is_null() {
+                        return if PyErr_Occurred().is_null() { 0 } else { -1 };
+                    }
+                    Py_DECREF(item);
+                    PyErr_Format(
+                        PyExc_ValueError,
+                        c"too many values to unpack (expected %d)".as_ptr(),
+                        n_before as c_int,
+                    );
+                    return -1;
+                }
+            };
+            let rest = PySequence_List(it);
+            if rest.is_null() {
+                return -1;
+            }
+            *out.add(n_before) = rest;
+            let n_rest = PyList_Size(rest);
+            if n_rest < n_after as isize {
+                PyErr_Format(
+                    PyExc_ValueError,
+                    c"not enough values to unpack (expected at least %d, got %zd)".as_ptr(),
+                    (n_before + n_after) as c_int,
+                    n_before as isize + n_rest,
+                );
+                return -1;
+            }
+            // Move the targets after the starred one out of the list.
+            for j in 0..n_after {
+                let item = PyList_GetItem(rest, n_rest - n_after as isize + j as isize);
+                *out.add(n_before + 1 + j) = item;
+            }
+            (*(rest as *mut PyVarObject)).ob_size = n_rest - n_after as isize;
+            0
+        })();
+        Py_DECREF(it);
+        r
+    }
+}
+
+fn unpack_sequence(out: *mut *mut PyObject, seq: *mut PyObject, n: u64) -> i64 {
+    unpack_iterable(out, seq, n as usize, None)
+}
+
+/// The argument of `UnpackEx` has the number of targets before the starred one in the low byte
+/// and the number after it in the next byte.
+fn unpack_ex(out: *mut *mut PyObject, seq: *mut PyObject, arg: u64) -> i64 {
+    unpack_iterable(out, seq, (arg & 0xff) as usize, Some((arg >> 8) as usize))
+}
+
+/// Native code offsets of labels and the jumps which refer to them. The label of the i-th
+/// instruction is i.
+struct Labels {
+    offsets: Vec<Option<usize>>,
+    // (index just after a rel32 operand, label)
+    fixups: Vec<(usize, usize)>,
+}
+
+impl Labels {
+    fn new(n_labels: usize) -> Labels {
+        Labels {
+            offsets: vec![None; n_labels],
+            fixups: Vec::new(),
+        }
+    }
+
+    fn new_label(&mut self) -> usize {
+        self.offsets.push(None);
+        self.offsets.len() - 1
+    }
+
+    fn bind(&mut self, label: usize, offset: usize) {
+        self.offsets[label] = Some(offset);
+    }
+
+    fn refer(&mut self, label: usize, rel32_end: usize) {
+        self.fixups.push((rel32_end, label));
+    }
+
+    fn is_referred(&self, label: usize) -> bool {
+        self.fixups.iter().any(|(_, l)| *l == label)
+    }
+
+    fn patch(&self, buf: *mut u8) {
+        for (rel32_end, label) in self.fixups.iter() {
+            let target = self.offsets[*label].expect("unbound label");
+            write_rel32(
+                buf,
+                rel32_end - 4,
+                (target as i64 - *rel32_end as i64) as i32,
+            );
+        }
+    }
+}
+
+/// Machine representation of a value which is kept out of a PyObject. A bool is 0 or 1.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Unboxed {
+    Int,
+    Float,
+    Bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ArithOp {
+    Add,
+    Sub,
+    Mul,
+    TrueDiv,
+    /// One of `Py_LT` to `Py_GE`
+    Compare(u32),
+}
+
+fn arith_op(code: &Bytecode, arg: u32) -> Option<ArithOp> {
+    match (code, arg) {
+        (Bytecode::BinaryAdd, _) => Some(ArithOp::Add),
+        (Bytecode::BinarySubtract, _) => Some(ArithOp::Sub),
+        (Bytecode::BinaryOp, NB_ADD | NB_INPLACE_ADD) => Some(ArithOp::Add),
+        (Bytecode::BinaryOp, NB_SUBTRACT | NB_INPLACE_SUBTRACT) => Some(ArithOp::Sub),
+        (Bytecode::BinaryOp, NB_MULTIPLY | NB_INPLACE_MULTIPLY) => Some(ArithOp::Mul),
+        (Bytecode::BinaryOp, NB_TRUE_DIVIDE | NB_INPLACE_TRUE_DIVIDE) => Some(ArithOp::TrueDiv),
+        (Bytecode::CompareOp, op) if op <= Py_GE as u32 => Some(ArithOp::Compare(op)),
+        _ => None,
+    }
+}
+
+/// Kind of `a <op> b` following CPython: ints stay ints except for true division, and an int
+/// meeting a float is promoted to float.
+fn arith_result(op: ArithOp, a: Unboxed, b: Unboxed) -> Option<Unboxed> {
+    match (op, a, b) {
+        (_, Unboxed::Bool, _) | (_, _, Unboxed::Bool) => None,
+        (ArithOp::Compare(_), _, _) => Some(Unboxed::Bool),
+        (ArithOp::TrueDiv, _, _) => Some(Unboxed::Float),
+        (_, Unboxed::Int, Unboxed::Int) => Some(Unboxed::Int),
+        _ => Some(Unboxed::Float),
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum UnaryArithOp {
+    Negative,
+    Invert,
+}
+
+fn unary_arith_op(code: &Bytecode) -> Option<UnaryArithOp> {
+    match code {
+        Bytecode::UnaryNegative => Some(UnaryArithOp::Negative),
+        Bytecode::UnaryInvert => Some(UnaryArithOp::Invert),
+        _ => None,
+    }
+}
+
+/// Kind of `<op> a`. Only ints can be inverted.
+fn unary_arith_result(op: UnaryArithOp, a: Unboxed) -> Option<Unboxed> {
+    match (op, a) {
+        (_, Unboxed::Bool) | (UnaryArithOp::Invert, Unboxed::Float) => None,
+        _ => Some(a),
+    }
+}
+
+/// A straight-line run of `LoadFast`, `LoadConst`, arithmetic and at most one final comparison
+/// which leaves exactly one value on the stack. Values inside a region never escape, so they are
+/// computed unboxed and only the result is boxed.
+struct ArithRegion {
+    end: usize,
+    kind: Unboxed,
+}
+
+/// `PyCellObject`, which pyo3 does not declare.
+#[repr(C)]
+struct PyCellObject {
+    ob_base: PyObject,
+    ob_ref: *mut PyObject,
+}
+
+/// The leading fields of `PyThreadState` in CPython 3.10, which pyo3 declares as opaque.
+#[repr(C)]
+struct ThreadState {
+    prev: *mut ThreadState,
+    next: *mut ThreadState,
+    interp: *mut c_void,
+    frame: *mut PyFrameObject,
+}
+
+/// Compiled code of a frame. It runs the frame as the thread's current one, as zero-argument
+/// `super()` and `sys._getframe()` expect, and returns the result or NULL with an exception set.
+type JitFunction = unsafe extern "C" fn(*mut ThreadState, *mut PyFrameObject) -> *mut PyObject;
+
+const PAGE_SIZE: usize = 4096;
+
+/// No instruction, including the unboxed and generic paths of an arithmetic region and the
+/// inline paths of a subscript, emits more than this per entry of its block stack, plus one.
+const MAX_BYTES_PER_CODE: usize = 512;
+
+/// Executable memory which code is compiled into. It is followed by an inaccessible page, so
+/// writing past the end faults instead of corrupting the heap, and is released when dropped.
+struct CodeBuffer {
+    ptr: *mut u8,
+    size: usize,
+}
+
+impl CodeBuffer {
+    fn new(size: usize) -> CodeBuffer {
+        let size = size.div_ceil(PAGE_SIZE) * PAGE_SIZE;
+        let ptr = unsafe { alloc(CodeBuffer::layout(size)) };
+        assert!(!ptr.is_null());
+        let mem = unsafe {
+            mprotect(
+                ptr as *const c_void,
+                size,
+                PROT_READ | PROT_WRITE | PROT_EXEC,
+            ) | mprotect(ptr.add(size) as *const c_void, PAGE_SIZE, PROT_NONE)
+        };
+        assert_eq!(mem, 0);
+        CodeBuffer { ptr, size }
+    }
+
+    fn layout(size: usize) -> Layout {
+        Layout::from_size_align(size + PAGE_SIZE, PAGE_SIZE).unwrap()
+    }
+}
+
+impl Drop for CodeBuffer {
+    fn drop(&mut self) {
+        unsafe {
+            let mem = mprotect(
+                self.ptr as *const c_void,
+                self.size + PAGE_SIZE,
+                PROT_READ | PROT_WRITE,
+            );
+            assert_eq!(mem, 0);
+            std::alloc::dealloc(self.ptr, CodeBuffer::layout(self.size));
+        }
+    }
+}
+
+// `b_type` of `PyTryBlock` in CPython 3.10
+const SETUP_FINALLY: c_int = 122;
+const EXCEPT_HANDLER: c_int = 257;
+
+/// An entry of the block stack of CPython 3.10. The block stack at each instruction is known
+/// while compiling, so an exception is unwound by code specific to it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Block {
+    /// Set up by `SetupFinally`. An exception unwinds the value stack to `level` values and
+    /// jumps to `handler`.
+    Finally { handler: usize, level: usize },
+    /// Pushed when entering a handler. The exception handled before is saved in the three
+    /// values above `level` and restored by `PopExcept` or unwinding.
+    ExceptHandler { level: usize },
+}
+
+/// Translates the bytecode of one frame to native code. The value stack lives on the native stack
+/// just below RBP, i.e. the i-th value is at [rbp-8*(i+1)], and holds strong references.
+struct Compiler {
+    /// Where the code is written, i.e. `buffer.ptr`
+    buf: *mut u8,
+    buffer: CodeBuffer,
+    offset: usize,
+    frame: *mut PyFrameObject,
+    code: Vec<(Bytecode, u32)>,
+    labels: Labels,
+    /// Where an exception raised by the instruction being compiled goes.
+    error_label: usize,
+    /// Where compiled code leaves with the result in RAX.
+    epilogue_label: usize,
+    depth: usize,
+    target_depths: Vec<Option<usize>>,
+    blocks: Vec<Block>,
+    target_blocks: Vec<Option<Vec<Block>>>,
+    /// Places which raise an exception: (label, instruction, block stack)
+    raises: Vec<(usize, usize, Vec<Block>)>,
+    /// Code which unwinds a block stack: (block stack, label which adds the frame to the
+    /// traceback first, label which does not)
+    unwinds: Vec<(Vec<Block>, usize, usize)>,
+    jump_targets: Vec<bool>,
+    reachable: bool,
+    /// Objects computed by constant folding. `LoadConst` refers to them with indices following
+    /// `co_consts`.
+    folded_consts: Vec<*mut PyObject>,
+    /// Globals which folded instructions depend on, checked before each instruction. The
+    /// compiled code refers to the guards, so they stay in place once compiling starts.
+    global_guards: Vec<Vec<GlobalGuard>>,
+    /// Side exits to the interpreter: (label, instruction to resume at, stack depth, block stack)
+    deopts: Vec<(usize, usize, usize, Vec<Block>)>,
+    /// Whether the frame is of a generator or coroutine, which keeps its state in the frame
+    /// while suspended.
+    is_generator: bool,
+    /// Whether an exception is thrown into the frame when it starts or resumes.
+    throwflag: bool,
+}
+
+impl Compiler {
+    fn new(
+        buffer: CodeBuffer,
+        offset: usize,
+        frame: *mut PyFrameObject,
+        code: Vec<(Bytecode, u32)>,
+        throwflag: bool,
+    ) -> Compiler {
+        let n = code.len();
+        let mut labels = Labels::new(n);
+        let error_label = labels.new_label();
+        let epilogue_label = labels.new_label();
+        let mut jump_targets = vec![false; n];
+        for (i, (c, arg)) in code.iter().enumerate() {
+            if let Some(target) = jump_target(i, c, *arg) {
+                if target < n {
+                    jump_targets[target] = true;
+                }
+            }
+        }
+        Compiler {
+            buf: buffer.ptr,
+            buffer,
+            offset,
+            frame,
+            code,
+            labels,
+            error_label,
+            epilogue_label,
+            depth: 0,
+            target_depths: vec![None; n],
+            blocks: Vec::new(),
+            target_blocks: vec![None; n],
+            raises: Vec::new(),
+            unwinds: Vec::new(),
+            jump_targets,
+            reachable: true,
+            folded_consts: Vec::new(),
+            global_guards: (0..n).map(|_| Vec::new()).collect(),
+            deopts: Vec::new(),
+            is_generator: unsafe { (*(*frame).f_code).co_flags } & (CO_GENERATOR | CO_COROUTINE)
+                != 0,
+            throwflag,
+        }
+    }
+
+    /// Returns the value of the i-th local when compiling. It may change while the compiled code
+    /// runs.
+    fn local(&self, i: u32) -> *mut PyObject {
+        unsafe { *self.local_addr(i) }
+    }
+
+    fn local_addr(&self, i: u32) -> *mut *mut PyObject {
+        unsafe {
+            let localsplus =
+                std::ptr::addr_of_mut!((*self.frame).f_localsplus) as *mut *mut PyObject;
+            localsplus.add(i as usize)
+        }
+    }
+
+    /// Whether the i-th local can be unbound when the compiled code reads it.
+    fn may_be_unbound(&self, i: u32) -> bool {
+        self.local(i).is_null() || self.code.contains(&(Bytecode::DeleteFast, i))
+    }
+
+    /// Loads the i-th local into RAX, raising UnboundLocalError if it is unbound.
+    fn compile_load_local(&mut self, i: u32) {
+        let unbound = self
+            .may_be_unbound(i)
+            .then_some(unbound_local as *const () as u64);
+        self.compile_load_slot(self.local_addr(i), i, unbound);
+    }
+
+    /// Returns the cell of the i-th cell or free variable, which stays the same while the frame
+    /// runs.
+    fn cell(&self, i: u32) -> Option<*mut PyCellObject> {
+        let n_locals = unsafe { (*(*self.frame).f_code).co_nlocals } as u32;
+        let cell = unsafe { *self.local_addr(n_locals + i) };
+        if cell.is_null() || unsafe { Py_TYPE(cell) != std::ptr::addr_of_mut!(PyCell_Type) } {
+            return None;
+        }
+        Some(cell as *mut PyCellObject)
+    }
+
+    /// Loads the value of a cell into RAX, raising with `unbound_deref` if it is empty.
+    fn compile_load_deref(&mut self, i: u32) -> Option<()> {
+        let cell = self.cell(i)?;
+        let addr = unsafe { std::ptr::addr_of_mut!((*cell).ob_ref) };
+        self.compile_load_slot(addr, i, Some(unbound_deref as *const () as u64));
+        Some(())
+    }
+
+    /// Loads the object at `addr` into RAX. If `unbound` is given, an empty slot is reported
+    /// by calling it with the frame and `i`, and raises.
+    fn compile_load_slot(&mut self, addr: *mut *mut PyObject, i: u32, unbound: Option<u64>) {
+        // MOV RAX, addr
+        self.offset = write_mov_rax(self.buf, self.offset, addr as u64);
+        // MOV RAX, [RAX]
+        self.offset = write_deref_rax(self.buf, self.offset);
+        let unbound = match unbound {
+            Some(unbound) => unbound,
+            None => return,
+        };
+        let bound_label = self.labels.new_label();
+        // TEST RAX, RAX
+        self.offset = write_test_rax_rax(self.buf, self.offset);
+        // JNE bound
+        self.offset = write_jne(self.buf, self.offset, 0);
+        self.labels.refer(bound_label, self.offset);
+        // MOV RDI, frame
+        self.offset = write_mov_rdi(self.buf, self.offset, self.frame as u64);
+        // MOV ESI, i
+        self.offset = write_mov_esi(self.buf, self.offset, i);
+        // MOV RAX, unbound
+        self.offset = write_mov_rax(self.buf, self.offset, unbound);
+        // CALL RAX
+        self.offset = write_call_rax(self.buf, self.offset, self.depth);
+        // JMP error
+        self.offset = write_jmp(self.buf, self.offset, 0);
+        self.labels.refer(self.error_label, self.offset);
+        self.labels.bind(bound_label, self.offset);
+    }
+
+    fn constant(&self, i: u32) -> *mut PyObject {
+        let co_consts = unsafe { (*(*self.frame).f_code).co_consts };
+        let n_consts = unsafe { PyTuple_Size(co_consts) } as usize;
+        match (i as usize).checked_sub(n_consts) {
+            Some(j) => self.folded_consts[j],
+            None => unsafe { PyTuple_GetItem(co_consts, i as isize) },
+        }
+    }
+
+    fn jump_to_instruction(&mut self, target: usize) -> Option<()> {
+        self.enter_instruction(target, self.depth, self.blocks.clone())?;
+        self.labels.refer(target, self.offset);
+        Some(())
+    }
+
+    /// Records the stack depth and block stack when the `target`-th instruction is reached from
+    /// elsewhere. Fails if they differ from an earlier record.
+    fn enter_instruction(&mut self, target: usize, depth: usize, blocks: Vec<Block>) -> Option<()> {
+        match self.target_depths.get(target)? {
+            Some(d) if *d != depth => return None,
+            _ => self.target_depths[target] = Some(depth),
+        }
+        match &self.target_blocks[target] {
+            Some(b) if *b != blocks => return None,
+            _ => self.target_blocks[target] = Some(blocks),
+        }
+        Some(())
+    }
+
+    /// Returns a new label for exceptions raised by the `i`-th instruction.
+    fn raise_label(&mut self, i: usize) -> usize {
+        let label = self.labels.new_label();
+        self.raises.push((label, i, self.blocks.clone()));
+        label
+    }
+
+    /// Returns the labels of the code which unwinds `blocks` for an exception, creating them on
+    /// first use: the first one adds the frame to the traceback with the instruction index in ESI,
+    /// the second one re-raises.
+    fn unwind_labels(&mut self, blocks: &[Block]) -> (usize, usize) {
+        if let Some((_, raise, reraise)) = self.unwinds.iter().find(|(b, _, _)| b == blocks) {
+            return (*raise, *reraise);
+        }
+        let labels = (self.labels.new_label(), self.labels.new_label());
+        self.unwinds.push((blocks.to_vec(), labels.0, labels.1));
+        labels
+    }
+
+    // CMP RAX, 0
+    // JE error
+    fn check_error(&mut self) {
+        self.offset = write_cmp_rax_0(self.buf, self.offset);
+        self.offset = write_je(self.buf, self.offset, 0);
+        self.labels.refer(self.error_label, self.offset);
+    }
+
+    // TEST RAX, RAX
+    // JS error
+    fn check_error_status(&mut self) {
+        self.offset = write_test_rax_rax(self.buf, self.offset);
+        self.offset = write_js(self.buf, self.offset, 0);
+        self.labels.refer(self.error_label, self.offset);
+    }
+
+    fn name(&self, i: u32) -> *mut PyObject {
+        unsafe { PyTuple_GetItem((*(*self.frame).f_code).co_names, i as isize) }
+    }
+
+    /// Computes `container[key]` for the top two values. An item of a list or tuple with an int
+    /// index is loaded inline and an exact dict is looked up directly, while other containers
+    /// go through `PyObject_GetItem`.
+    fn compile_binary_subscr(&mut self) -> Option<()> {
+        let p_start = self.buf;
+        let seq_label = self.labels.new_label();
+        let not_list_label = self.labels.new_label();
+        let slow_label = self.labels.new_label();
+        let call_done_label = self.labels.new_label();
+        let done_label = self.labels.new_label();
+
+        // MOV RDX, [RSP+8] (container)
+        self.offset = write_load_rdx_second(p_start, self.offset);
+        // MOV RCX, &PyDict_Type
+        let dict_type = std::ptr::addr_of_mut!(PyDict_Type);
+        self.offset = write_mov_rcx(p_start, self.offset, dict_type as u64);
+        // CMP [RDX+8], RCX
+        self.offset = write_cmp_ob_type_rdx_rcx(p_start, self.offset);
+        // JNE seq
+        self.offset = write_jne(p_start, self.offset, 0);
+        self.labels.refer(seq_label, self.offset);
+        // POP RSI
+        self.offset = write_pop_rsi(p_start, self.offset);
+        // POP RDI
+        self.offset = write_pop_rdi(p_start, self.offset);
+        // MOV RAX, dict_subscr
+        self.offset = write_mov_rax(p_start, self.offset, dict_subscr as *const () as u64);
+        // CALL RAX
+        self.offset = write_call_rax(p_start, self.offset, self.depth.checked_sub(2)?);
+        // JMP call_done
+        self.offset = write_jmp(p_start, self.offset, 0);
+        self.labels.refer(call_done_label, self.offset);
+
+        // The index must be an int of at most one digit.
+        self.labels.bind(seq_label, self.offset);
+        // MOV RAX, [RSP] (key)
+        self.offset = write_load_rax_top(p_start, self.offset);
+        // MOV RCX, &PyLong_Type
+        let long_type = std::ptr::addr_of_mut!(PyLong_Type);
+        self.offset = write_mov_rcx(p_start, self.offset, long_type as u64);
+        // CMP [RAX+8], RCX
+        self.offset = write_cmp_ob_type_rcx(p_start, self.offset);
+        // JNE slow
+        self.offset = write_jne(p_start, self.offset, 0);
+        self.labels.refer(slow_label, self.offset);
+        let (offset, jump_end) = write_unbox_compact_long(p_start, self.offset);
+        self.offset = offset;
+        self.labels.refer(slow_label, jump_end);
+        // MOV RDX, [RSP+8] (container)
+        self.offset = write_load_rdx_second(p_start, self.offset);
+
+        // MOV RCX, &PyList_Type
+        let list_type = std::ptr::addr_of_mut!(PyList_Type);
+        self.offset = write_mov_rcx(p_start, self.offset, list_type as u64);
+        // CMP [RDX+8], RCX
+        self.offset = write_cmp_ob_type_rdx_rcx(p_start, self.offset);
+        // JNE not_list
+        self.offset = write_jne(p_start, self.offset, 0);
+        self.labels.refer(not_list_label, self.offset);
+        self.offset = write_check_sequence_index(p_start, self.offset);
+        self.labels.refer(slow_label, self.offset);
+        self.offset = write_load_list_item(p_start, self.offset);
+        let list_done = self.offset;
+        // JMP item
+        self.offset = write_jmp(p_start, self.offset, 0);
+
+        self.labels.bind(not_list_label, self.offset);
+        // MOV RCX, &PyTuple_Type
+        let tuple_type = std::ptr::addr_of_mut!(PyTuple_Type);
+        self.offset = write_mov_rcx(p_start, self.offset, tuple_type as u64);
+        // CMP [RDX+8], RCX
+        self.offset = write_cmp_ob_type_rdx_rcx(p_start, self.offset);
+        // JNE slow
+        self.offset = write_jne(p_start, self.offset, 0);
+        self.labels.refer(slow_label, self.offset);
+        self.offset = write_check_sequence_index(p_start, self.offset);
+        self.labels.refer(slow_label, self.offset);
+        self.offset = write_load_tuple_item(p_start, self.offset);
+        write_rel32(p_start, list_done + 1, (self.offset - list_done - 5) as i32);
+
+        // The item replaces the key on the stack while the key and the container are released.
+        // INCREF RAX
+        self.offset = write_incref_rax(p_start, self.offset);
+        // MOV RDI, [RSP]
+        self.offset = write_load_rdi_stack(p_start, self.offset, 0);
+        // MOV [RSP], RAX
+        self.offset = write_store_rax_top(p_start, self.offset);
+        // DECREF RDI
+        self.offset = write_decref_rdi(p_start, self.offset, self.depth);
+        // MOV RDI, [RSP+8]
+        self.offset = write_load_rdi_stack(p_start, self.offset, 1);
+        // DECREF RDI
+        self.offset = write_decref_rdi(p_start, self.offset, self.depth);
+        // POP RAX
+        self.offset = write_pop_rax(p_start, self.offset);
+        // MOV [RSP], RAX
+        self.offset = write_store_rax_top(p_start, self.offset);
+        // JMP done
+        self.offset = write_jmp(p_start, self.offset, 0);
+        self.labels.refer(done_label, self.offset);
+
+        self.labels.bind(slow_label, self.offset);
+        // POP RSI
+        self.offset = write_pop_rsi(p_start, self.offset);
+        // POP RDI
+        self.offset = write_pop_rdi(p_start, self.offset);
+        // MOV RAX, binary_subscr
+        self.offset = write_mov_rax(p_start, self.offset, binary_subscr as *const () as u64);
+        // CALL RAX
+        self.offset = write_call_rax(p_start, self.offset, self.depth.checked_sub(2)?);
+
+        self.labels.bind(call_done_label, self.offset);
+        self.check_error();
+        // PUSH RAX
+        self.offset = write_push_rax(p_start, self.offset);
+        self.labels.bind(done_label, self.offset);
+        self.depth -= 1;
+        Some(())
+    }
+
+    /// Pops two values and sets RAX to 1 if they are identical, or not identical if `invert` is
+    /// 1, and to 0 otherwise.
+    fn compile_is_op(&mut self, invert: u32) -> Option<()> {
+        let p_start = self.buf;
+        // POP RCX
+        self.offset = write_pop_rcx(p_start, self.offset);
+        // POP RDI
+        self.offset = write_pop_rdi(p_start, self.offset);
+        // CMP RDI, RCX
+        self.offset = write_bytes(p_start, self.offset, &[0x48, 0x39, 0xcf]);
+        let cc = if invert == 1 { CC_NE } else { CC_E };
+        self.offset = write_setcc_al(p_start, self.offset, cc);
+        self.offset = write_movzx_eax_al(p_start, self.offset);
+        // The operands are released while the result and the second operand are kept on the
+        // native stack.
+        // PUSH RAX
+        self.offset = write_push_rax(p_start, self.offset);
+        // PUSH RCX
+        self.offset = write_bytes(p_start, self.offset, &[0x51]);
+        // DECREF RDI
+        self.offset = write_decref_rdi(p_start, self.offset, self.depth);
+        // POP RDI
+        self.offset = write_pop_rdi(p_start, self.offset);
+        // DECREF RDI
+        self.offset = write_decref_rdi(p_start, self.offset, self.depth.checked_sub(1)?);
+        // POP RAX
+        self.offset = write_pop_rax(p_start, self.offset);
+        Some(())
+    }
+
+    /// Compiles `IsOp` followed by `PopJumpIfFalse` or `PopJumpIfTrue` as a branch on the
+    /// identity test, without creating a bool.
+    fn compile_is_op_branch(&mut self, i: usize) -> Option<()> {
+        let (_, invert) = self.code[i];
+        let (jump, arg) = self.code[i + 1];
+        self.compile_is_op(invert)?;
+        self.depth = self.depth.checked_sub(2)?;
+        self.labels.bind(i + 1, self.offset);
+        // TEST EAX, EAX
+        self.offset = write_bytes(self.buf, self.offset, &[0x85, 0xc0]);
+        self.offset = if jump == Bytecode::PopJumpIfTrue {
+            write_jne(self.buf, self.offset, 0)
+        } else {
+            write_je(self.buf, self.offset, 0)
+        };
+        self.jump_to_instruction(jump_target(i + 1, &jump, arg)?)
+    }
+
+    /// Calls `helper(rsp, arg)`, which consumes the top `n_popped` values, and pushes the result
+    /// if `pushes`. Otherwise the helper returns a negative status for an exception.
+    fn compile_stack_call(
+        &mut self,
+        helper: u64,
+        arg: u32,
+        n_popped: u32,
+        pushes: bool,
+    ) -> Option<()> {
+        // MOV RDI, RSP
+        self.offset = write_mov_rsp_to_rdi(self.buf, self.offset);
+        // MOV ESI, arg
+        self.offset = write_mov_esi(self.buf, self.offset, arg);
+        // MOV RAX, helper
+        self.offset = write_mov_rax(self.buf, self.offset, helper);
+        // CALL RAX
+        self.offset = write_call_rax(self.buf, self.offset, self.depth);
+        self.depth = self.depth.checked_sub(n_popped as usize)?;
+        // LEA RSP, [RBP-8*depth]
+        self.offset = write_reset_rsp(self.buf, self.offset, self.depth);
+        if pushes {
+            self.check_error();
+            // PUSH RAX
+            self.offset = write_push_rax(self.buf, self.offset);
+            self.depth += 1;
+        } else {
+            self.check_error_status();
+        }
+        Some(())
+    }
+
+    /// Calls a formatting helper as `compile_stack_call` does. If `str_is_result` is set, a str
+    /// on top of the stack is left there as the result without the call.
+    fn compile_format(
+        &mut self,
+        helper: u64,
+        arg: u32,
+        n_popped: u32,
+        str_is_result: bool,
+    ) -> Option<()> {
+        let done_label = self.labels.new_label();
+        if str_is_result {
+            // MOV RAX, [RSP]
+            self.offset = write_load_rax_top(self.buf, self.offset);
+            // MOV RCX, &PyUnicode_Type
+            let unicode_type = std::ptr::addr_of_mut!(PyUnicode_Type);
+            self.offset = write_mov_rcx(self.buf, self.offset, unicode_type as u64);
+            // CMP [RAX+8], RCX
+            self.offset = write_cmp_ob_type_rcx(self.buf, self.offset);
+            // JE done
+            self.offset = write_je(self.buf, self.offset, 0);
+            self.labels.refer(done_label, self.offset);
+        }
+        self.compile_stack_call(helper, arg, n_popped, true)?;
+        self.labels.bind(done_label, self.offset);
+        Some(())
+    }
+
+    /// Returns the attribute cache of the i-th instruction, or None if the code object cannot hold
+    /// caches.
+    fn attr_cache(&self, i: usize) -> Option<*mut AttrCache> {
+        let (code, arg) = self.code[i];
+        let name = self.name(arg);
+        let caches = unsafe { attr_caches((*self.frame).f_code as *mut PyObject)? };
+        let cache = caches
+            .entry((i, code as u32))
+            .or_insert_with(|| Box::new(AttrCache::new(name)));
+        Some(&mut **cache as *mut AttrCache)
+    }
+
+    /// Makes room for `bytes` more bytes of code, moving the code compiled so far to a larger
+    /// buffer if needed. It refers to itself only by relative offsets, so it can be moved.
+    fn reserve(&mut self, bytes: usize) {
+        if self.offset + bytes <= self.buffer.size {
+            return;
+        }
+        let mut size = self.buffer.size * 2;
+        while self.offset + bytes > size {
+            size *= 2;
+        }
+        let buffer = CodeBuffer::new(size);
+        unsafe { std::ptr::copy_nonoverlapping(self.buf, buffer.ptr, self.offset) };
+        self.buf = buffer.ptr;
+        self.buffer = buffer;
+    }
+
+    fn compile(&mut self) -> Option<()> {
+        if unsafe { (*(*self.frame).f_code).co_flags } & CO_ASYNC_GENERATOR != 0 {
+            info!("Async generators are not supported");
+            return None;
+        }
+        self.fold_constants();
+        self.fuse_zero_super();
+        self.fuse_return_const();
+        self.fuse_none_jump();
+        if self.is_generator {
+            let n_blocks = unsafe { (*self.frame).f_iblock } as usize;
+            self.reserve(MAX_BYTES_PER_CODE * (n_blocks + 1));
+            self.compile_resume()?;
+        }
+
+        let mut i = 0;
+        while i < self.code.len() {
+            self.labels.bind(i, self.offset);
+            if let Some(d) = self.target_depths[i] {
+                self.depth = d;
+                self.reachable = true;
+            }
+            if let Some(blocks) = &self.target_blocks[i] {
+                self.blocks = blocks.clone();
+            }
+            if !self.reachable {
+                i += 1;
+                continue;
+            }
+            // Recorded for backward jumps
+            self.target_depths[i] = Some(self.depth);
+            self.target_blocks[i] = Some(self.blocks.clone());
+            self.error_label = self.raise_label(i);
+            self.reserve(MAX_BYTES_PER_CODE * (self.blocks.len() + 2));
+            if !self.global_guards[i].is_empty() {
+                self.compile_global_guards(i);
+            }
+            if self.code[i].0 == Bytecode::IsOp
+                && matches!(
+                    self.code.get(i + 1),
+                    Some((Bytecode::PopJumpIfFalse | Bytecode::PopJumpIfTrue, _))
+                )
+                && !self.jump_targets[i + 1]
+            {
+                self.compile_is_op_branch(i)?;
+                i += 2;
+                continue;
+            }
+            if let Some(region) = self.find_arith_region(i) {
+                self.reserve(MAX_BYTES_PER_CODE * (region.end - i + 1));
+                self.compile_arith_region(i, &region)?;
+                i = region.end;
+                continue;
+            }
+            self.compile_instruction(i)?;
+            i += 1;
+        }
+
+        for (label, index, blocks) in std::mem::take(&mut self.raises) {
+            if !self.labels.is_referred(label) {
+                continue;
+            }
+            self.reserve(MAX_BYTES_PER_CODE);
+            self.labels.bind(label, self.offset);
+            let (raise, _) = self.unwind_labels(&blocks);
+            // MOV ESI, index
+            self.offset = write_mov_esi(self.buf, self.offset, index as u32);
+            // JMP raise
+            self.offset = write_jmp(self.buf, self.offset, 0);
+            self.labels.refer(raise, self.offset);
+        }
+        for (blocks, raise, reraise) in std::mem::take(&mut self.unwinds) {
+            self.reserve(MAX_BYTES_PER_CODE * (blocks.len() + 1));
+            self.compile_unwind(&blocks, raise, reraise);
+        }
+
+        for (label, index, depth, blocks) in std::mem::take(&mut self.deopts) {
+            self.reserve(MAX_BYTES_PER_CODE * (blocks.len() + 1));
+            self.labels.bind(label, self.offset);
+            self.compile_block_setup(&blocks, index, depth);
+            self.offset = write_mov_rdi(self.buf, self.offset, self.frame as u64);
+            self.offset = write_mov_esi(self.buf, self.offset, index as u32);
+            self.offset = write_mov_edx(self.buf, self.offset, depth as u32);
+            self.offset = write_mov_rbp_to_rcx(self.buf, self.offset);
+            self.offset = write_mov_rax(self.buf, self.offset, deopt as *const () as u64);
+            self.offset = write_call_rax(self.buf, self.offset, depth);
+            self.jump_to_epilogue();
+        }
+
+        self.reserve(MAX_BYTES_PER_CODE);
+        self.labels.bind(self.epilogue_label, self.offset);
+        self.offset = write_epilogue(self.buf, self.offset, self.frame);
+        self.labels.patch(self.buf);
+        Some(())
+    }
+
+    /// Emits the code which unwinds `blocks` for an exception as CPython 3.10 does: the value
+    /// stack is unwound block by block, restoring the exception handled before for each except
+    /// handler, until a `SetupFinally` handler is entered. If there is none, the remaining values
+    /// are released and NULL is returned.
+    ///
+    /// Exceptions are checked at different depths within an instruction, so the depth is not
+    /// known here. The top of the values is kept in RBX while RSP is aligned below it.
+    fn compile_unwind(&mut self, blocks: &[Block], raise: usize, reraise: usize) {
+        let unwind_label = self.labels.new_label();
+        self.labels.bind(reraise, self.offset);
+        // MOV RBX, RSP
+        // AND RSP, -16
+        self.offset = write_bytes(self.buf, self.offset, &[0x48, 0x89, 0xe3]);
+        self.offset = write_bytes(self.buf, self.offset, &[0x48, 0x83, 0xe4, 0xf0]);
+        // JMP unwind
+        self.offset = write_jmp(self.buf, self.offset, 0);
+        self.labels.refer(unwind_label, self.offset);
+
+        self.labels.bind(raise, self.offset);
+        // MOV RBX, RSP
+        // AND RSP, -16
+        self.offset = write_bytes(self.buf, self.offset, &[0x48, 0x89, 0xe3]);
+        self.offset = write_bytes(self.buf, self.offset, &[0x48, 0x83, 0xe4, 0xf0]);
+        // MOV RDI, frame
+        self.offset = write_mov_rdi(self.buf, self.offset, self.frame as u64);
+        // MOV RAX, raise_here
+        self.offset = write_mov_rax(self.buf, self.offset, raise_here as *const () as u64);
+        // CALL RAX
+        self.offset = write_call_rax(self.buf, self.offset, 0);
+        self.labels.bind(unwind_label, self.offset);
+        for block in blocks.iter().rev() {
+            let (helper, level) = match *block {
+                Block::ExceptHandler { level } => {
+                    (unwind_except_handler as *const () as u64, level)
+                }
+                Block::Finally { level, .. } => (unwind_stack as *const () as u64, level),
+            };
+            // MOV RDI, RBX
+            self.offset = write_bytes(self.buf, self.offset, &[0x48, 0x89, 0xdf]);
+            // MOV RSI, RBP
+            self.offset = write_mov_rbp_to_rsi(self.buf, self.offset);
+            // MOV EDX, level
+            self.offset = write_mov_edx(self.buf, self.offset, level as u32);
+            // MOV RAX, helper
+            self.offset = write_mov_rax(self.buf, self.offset, helper);
+            // CALL RAX
+            self.offset = write_call_rax(self.buf, self.offset, 0);
+            if let Block::Finally { handler, level } = *block {
+                // LEA RSP, [RBP-8*(level+6)]
+                self.offset = write_reset_rsp(self.buf, self.offset, level + 6);
+                // MOV RDI, RSP
+                self.offset = write_mov_rsp_to_rdi(self.buf, self.offset);
+                // MOV RAX, enter_handler
+                self.offset =
+                    write_mov_rax(self.buf, self.offset, enter_handler as *const () as u64);
+                // CALL RAX
+                self.offset = write_call_rax(self.buf, self.offset, level + 6);
+                // JMP handler
+                self.offset = write_jmp(self.buf, self.offset, 0);
+                self.labels.refer(handler, self.offset);
+                return;
+            }
+            // LEA RBX, [RBP-8*level]
+            self.offset = write_bytes(self.buf, self.offset, &[0x48, 0x8d, 0x9d]);
+            self.offset = write_rel32(self.buf, self.offset, -8 * level as i32);
+        }
+        // MOV RDI, RBX
+        self.offset = write_bytes(self.buf, self.offset, &[0x48, 0x89, 0xdf]);
+        // MOV RSI, RBP
+        self.offset = write_mov_rbp_to_rsi(self.buf, self.offset);
+        // MOV EDX, 0
+        self.offset = write_mov_edx(self.buf, self.offset, 0);
+        // MOV RAX, unwind_stack
+        self.offset = write_mov_rax(self.buf, self.offset, unwind_stack as *const () as u64);
+        // CALL RAX
+        self.offset = write_call_rax(self.buf, self.offset, 0);
+        self.compile_finish_frame(FRAME_RAISED, 0);
+        // Pending exception: return NULL
+        self.offset = write_zero_rax(self.buf, self.offset);
+        self.jump_to_epilogue();
+    }
+
+    // JMP epilogue
+    fn jump_to_epilogue(&mut self) {
+        self.offset = write_jmp(self.buf, self.offset, 0);
+        self.labels.refer(self.epilogue_label, self.offset);
+    }
+
+    /// Pops the top three values, which are an exception, into a helper which takes them at RSP.
+    fn compile_pop_exception(&mut self, helper: u64) -> Option<()> {
+        // MOV RDI, RSP
+        self.offset = write_mov_rsp_to_rdi(self.buf, self.offset);
+        // MOV RAX, helper
+        self.offset = write_mov_rax(self.buf, self.offset, helper);
+        // CALL RAX
+        self.offset = write_call_rax(self.buf, self.offset, self.depth);
+        self.depth = self.depth.checked_sub(3)?;
+        // LEA RSP, [RBP-8*depth]
+        self.offset = write_reset_rsp(self.buf, self.offset, self.depth);
+        Some(())
+    }
+
+    /// Emits code which sets up `blocks` as the block stack of the frame when leaving compiled
+    /// code at the `index`-th instruction, so that the interpreter can continue from there.
+    /// `depth` is the number of values on the native stack.
+    fn compile_block_setup(&mut self, blocks: &[Block], index: usize, depth: usize) {
+        for block in blocks {
+            let (b_type, handler, level) = match *block {
+                Block::Finally { handler, level } => (SETUP_FINALLY, handler, level),
+                Block::ExceptHandler { level } => (EXCEPT_HANDLER, index, level),
+            };
+            self.offset = write_mov_rdi(self.buf, self.offset, self.frame as u64);
+            self.offset = write_mov_esi(self.buf, self.offset, b_type as u32);
+            self.offset = write_mov_edx(self.buf, self.offset, handler as u32);
+            self.offset = write_mov_rcx(self.buf, self.offset, level as u64);
+            self.offset = write_mov_rax(
+                self.buf,
+                self.offset,
+                PyFrame_BlockSetup as *const () as u64,
+            );
+            self.offset = write_call_rax(self.buf, self.offset, depth);
+        }
+    }
+
+    /// Emits the entry of a generator frame, which starts at the instruction after `f_lasti`
+    /// with the frame's value stack and block stack. Those are left by the first call, a
+    /// `YieldValue` or `YieldFrom` of compiled code or the interpreter. A thrown exception is
+    /// raised there instead.
+    fn compile_resume(&mut self) -> Option<()> {
+        let (lasti, depth) = unsafe { ((*self.frame).f_lasti, (*self.frame).f_stackdepth) };
+        let resume = (lasti + 1) as usize;
+        let depth = depth as usize;
+        let mut blocks = Vec::new();
+        for k in 0..unsafe { (*self.frame).f_iblock } as usize {
+            let b = unsafe { (*self.frame).f_blockstack[k] };
+            blocks.push(match b.b_type {
+                SETUP_FINALLY => Block::Finally {
+                    handler: b.b_handler as usize,
+                    level: b.b_level as usize,
+                },
+                EXCEPT_HANDLER => Block::ExceptHandler {
+                    level: b.b_level as usize,
+                },
+                _ => return None,
+            });
+        }
+        // LEA RSP, [RBP-8*depth]
+        self.offset = write_reset_rsp(self.buf, self.offset, depth);
+        // MOV RDI, frame
+        self.offset = write_mov_rdi(self.buf, self.offset, self.frame as u64);
+        // MOV RSI, RSP
+        self.offset = write_bytes(self.buf, self.offset, &[0x48, 0x89, 0xe6]);
+        // MOV RAX, resume_frame
+        self.offset = write_mov_rax(self.buf, self.offset, resume_frame as *const () as u64);
+        // CALL RAX
+        self.offset = write_call_rax(self.buf, self.offset, depth);
+        self.enter_instruction(resume, depth, blocks.clone())?;
+        if self.throwflag {
+            let (raise, _) = self.unwind_labels(&blocks);
+            // MOV ESI, lasti
+            self.offset = write_mov_esi(self.buf, self.offset, lasti.max(0) as u32);
+            // JMP raise
+            self.offset = write_jmp(self.buf, self.offset, 0);
+            self.labels.refer(raise, self.offset);
+        } else {
+            // JMP resume
+            self.offset = write_jmp(self.buf, self.offset, 0);
+            self.labels.refer(resume, self.offset);
+        }
+        Some(())
+    }
+
+    /// Leaves compiled code yielding `sp[0]` and keeping the `depth` values below it in the
+    /// frame. The frame resumes at the instruction after `lasti`.
+    fn compile_suspend(&mut self, depth: usize, lasti: usize) {
+        let blocks = self.blocks.clone();
+        self.compile_block_setup(&blocks, lasti, depth + 1);
+        // MOV RDI, frame
+        self.offset = write_mov_rdi(self.buf, self.offset, self.frame as u64);
+        // MOV RSI, RSP
+        self.offset = write_bytes(self.buf, self.offset, &[0x48, 0x89, 0xe6]);
+        // MOV EDX, depth
+        self.offset = write_mov_edx(self.buf, self.offset, depth as u32);
+        // MOV ECX, lasti
+        self.offset = write_mov_rcx(self.buf, self.offset, lasti as u64);
+        // MOV RAX, suspend_frame
+        self.offset = write_mov_rax(self.buf, self.offset, suspend_frame as *const () as u64);
+        // CALL RAX
+        self.offset = write_call_rax(self.buf, self.offset, depth + 1);
+        self.jump_to_epilogue();
+    }
+
+    /// Marks a generator frame as finished with `state`. `depth` is the number of values on the
+    /// native stack.
+    fn compile_finish_frame(&mut self, state: i8, depth: usize) {
+        if !self.is_generator {
+            return;
+        }
+        // MOV RDI, frame
+        self.offset = write_mov_rdi(self.buf, self.offset, self.frame as u64);
+        // MOV ESI, state
+        self.offset = write_mov_esi(self.buf, self.offset, state as u32);
+        // MOV RAX, finish_frame
+        self.offset = write_mov_rax(self.buf, self.offset, finish_frame as *const () as u64);
+        // CALL RAX
+        self.offset = write_call_rax(self.buf, self.offset, depth);
+    }
+
+    /// Returns a label which leaves compiled code and resumes the interpreter at the `i`-th
+    /// instruction with the current value stack.
+    fn deopt_label(&mut self, i: usize) -> usize {
+        let mut start = i;
+        while start > 0 && self.code[start - 1].0 == Bytecode::ExtendedArg {
+            start -= 1;
+        }
+        let label = self.labels.new_label();
+        self.deopts
+            .push((label, start, self.depth, self.blocks.clone()));
+        label
+    }
+
+    /// Leaves for the interpreter at instruction `i` if a global which folded instructions depend
+    /// on is no longer bound to the value they were folded with.
+    fn compile_global_guards(&mut self, i: usize) {
+        let p_start = self.buf;
+        let globals = unsafe { (*self.frame).f_globals };
+        let label = self.deopt_label(i);
+        for k in 0..self.global_guards[i].len() {
+            let guard = &mut self.global_guards[i][k] as *mut GlobalGuard;
+            let done_label = self.labels.new_label();
+            let tag =
+                unsafe { std::ptr::addr_of!((*(globals as *mut PyDictObject)).ma_version_tag) };
+            // MOV RAX, &globals.ma_version_tag
+            self.offset = write_mov_rax(p_start, self.offset, tag as u64);
+            // MOV RAX, [RAX]
+            self.offset = write_deref_rax(p_start, self.offset);
+            // MOV RCX, &guard.version
+            let version = unsafe { std::ptr::addr_of!((*guard).version) };
+            self.offset = write_mov_rcx(p_start, self.offset, version as u64);
+            // CMP [RCX], RAX
+            self.offset = write_cmp_deref_rcx_rax(p_start, self.offset);
+            // JE done
+            self.offset = write_je(p_start, self.offset, 0);
+            self.labels.refer(done_label, self.offset);
+            // MOV RDI, guard
+            self.offset = write_mov_rdi(p_start, self.offset, guard as u64);
+            // MOV RAX, global_unchanged
+            self.offset = write_mov_rax(p_start, self.offset, global_unchanged as *const () as u64);
+            // CALL RAX
+            self.offset = write_call_rax(p_start, self.offset, self.depth);
+            // TEST RAX, RAX
+            self.offset = write_test_rax_rax(p_start, self.offset);
+            // JE deopt
+            self.offset = write_je(p_start, self.offset, 0);
+            self.labels.refer(label, self.offset);
+            self.labels.bind(done_label, self.offset);
+        }
+    }
+
+    // MOV RAX, &dict.ma_version_tag
+    // MOV RCX, version
+    // CMP [RAX], RCX
+    // JNE label
+    fn compile_dict_guard(&mut self, dict: *mut PyObject, version: u64, label: usize) {
+        let tag = unsafe { std::ptr::addr_of!((*(dict as *mut PyDictObject)).ma_version_tag) };
+        self.offset = write_mov_rax(self.buf, self.offset, tag as u64);
+        self.offset = write_mov_rcx(self.buf, self.offset, version);
+        self.offset = write_cmp_deref_rax_rcx(self.buf, self.offset);
+        self.offset = write_jne(self.buf, self.offset, 0);
+        self.labels.refer(label, self.offset);
+    }
+
+    /// Pushes a global. The value found while compiling is used as long as neither the globals
+    /// nor, for a builtin, the builtins dict has changed since. Otherwise the name is looked up
+    /// again.
+    fn compile_load_global(&mut self, arg: u32) {
+        let name = self.name(arg);
+        let (globals, builtins) = unsafe { ((*self.frame).f_globals, (*self.frame).f_builtins) };
+        let slow_label = self.labels.new_label();
+        let done_label = self.labels.new_label();
+        let mut value = unsafe { PyDict_GetItem(globals, name) };
+        self.compile_dict_guard(globals, dict_version(globals), slow_label);
+        if value.is_null() {
+            value = unsafe { PyDict_GetItem(builtins, name) };
+            self.compile_dict_guard(builtins, dict_version(builtins), slow_label);
+        }
+        if value.is_null() {
+            info!("Global not found:{:?}", arg);
+        } else {
+            // MOV RAX, value
+            self.offset = write_mov_rax(self.buf, self.offset, value as u64);
+            // INCREF RAX
+            self.offset = write_incref_rax(self.buf, self.offset);
+            // JMP done
+            self.offset = write_jmp(self.buf, self.offset, 0);
+            self.labels.refer(done_label, self.offset);
+        }
+
+        self.labels.bind(slow_label, self.offset);
+        // MOV RDI, frame
+        self.offset = write_mov_rdi(self.buf, self.offset, self.frame as u64);
+        // MOV RSI, name
+        self.offset = write_mov_rsi(self.buf, self.offset, name as u64);
+        // MOV RAX, load_global
+        self.offset = write_mov_rax(self.buf, self.offset, load_global as *const () as u64);
+        // CALL RAX
+        self.offset = write_call_rax(self.buf, self.offset, self.depth);
+        self.check_error();
+
+        self.labels.bind(done_label, self.offset);
+        // PUSH RAX
+        self.offset = write_push_rax(self.buf, self.offset);
+        self.depth += 1;
+    }
+
+    /// Returns the value of an instruction which loads a constant, and the name of the global it
+    /// is read from, if any. Only globals of immutable builtin types are treated as constants.
+    fn const_source(&self, i: usize) -> Option<(*mut PyObject, Option<*mut PyObject>)> {
+        let (code, arg) = self.code[i];
+        match code {
+            Bytecode::LoadConst => Some((self.constant(arg), None)),
+            Bytecode::LoadGlobal => unsafe {
+                let name = self.name(arg);
+                let globals = (*self.frame).f_globals;
+                let value = PyDict_GetItem(globals, name);
+                if value.is_null() || !is_foldable(value) {
+                    return None;
+                }
+                Some((value, Some(name)))
+            },
+            _ => None,
+        }
+    }
+
+    /// Replaces `super().name` of CPython 3.10, i.e. `LOAD_GLOBAL super`, `CALL_FUNCTION 0` and
+    /// `LOAD_ATTR` or `LOAD_METHOD`, by `LoadZeroSuperAttr` or `LoadZeroSuperMethod`, so that no
+    /// super object is created. The call becomes `Nop`.
+    fn fuse_zero_super(&mut self) {
+        for i in 0..self.code.len().saturating_sub(2) {
+            let (code, arg) = self.code[i];
+            if code != Bytecode::LoadGlobal
+                || self.code[i + 1] != (Bytecode::CallFunction, 0)
+                || self.jump_targets[i + 1]
+                || self.jump_targets[i + 2]
+                || unsafe { PyUnicode_CompareWithASCIIString(self.name(arg), c"super".as_ptr()) }
+                    != 0
+            {
+                continue;
+            }
+            let fused = match self.code[i + 2].0 {
+                Bytecode::LoadAttr => Bytecode::LoadZeroSuperAttr,
+                Bytecode::LoadMethod => Bytecode::LoadZeroSuperMethod,
+                _ => continue,
+            };
+            self.code[i + 1] = (Bytecode::Nop, 0);
+            self.code[i + 2].0 = fused;
+        }
+    }
+
+    /// Replaces `LOAD_CONST` followed by `RETURN_VALUE` of CPython 3.10 by `ReturnConst` of 3.12,
+    /// including constants made by `fold_constants`. The load becomes `Nop`.
+    fn fuse_return_const(&mut self) {
+        for i in 0..self.code.len() {
+            if self.code[i].0 != Bytecode::ReturnValue {
+                continue;
+            }
+            let j = match self.prev_instruction(i) {
+                Some(j) if !self.jump_targets[j + 1..=i].contains(&true) => j,
+                _ => continue,
+            };
+            if let (Bytecode::LoadConst, arg) = self.code[j] {
+                self.code[j] = (Bytecode::Nop, 0);
+                self.code[i] = (Bytecode::ReturnConst, arg);
+            }
+        }
+    }
+
+    /// Compiles a jump on the truth of the top value: `PopJumpIfFalse` and `PopJumpIfTrue`, which
+    /// pop it, and `JumpIfFalseOrPop` and `JumpIfTrueOrPop`, which keep it when jumping and pop
+    /// it otherwise. `True`, `False` and `None` are tested by identity, other objects by
+    /// `PyObject_IsTrue`.
+    fn compile_truth_jump(&mut self, i: usize, code: Bytecode, arg: u32) -> Option<()> {
+        let p_start = self.buf;
+        let target = jump_target(i, &code, arg)?;
+        let jumps_if = matches!(code, Bytecode::PopJumpIfTrue | Bytecode::JumpIfTrueOrPop);
+        let keeps = matches!(code, Bytecode::JumpIfFalseOrPop | Bytecode::JumpIfTrueOrPop);
+        let depth = self.depth;
+        self.depth = depth.checked_sub(1)?;
+        let (if_true, if_false) = if jumps_if {
+            (target, i + 1)
+        } else {
+            (i + 1, target)
+        };
+        let pop_label = self.labels.new_label();
+        let done_label = self.labels.new_label();
+        if keeps {
+            // MOV RAX, [RSP]
+            self.offset = write_load_rax_top(p_start, self.offset);
+        } else {
+            // POP RAX
+            self.offset = write_pop_rax(p_start, self.offset);
+        }
+        // The singletons never reach refcount 0, so they are released inline.
+        for (singleton, truth) in [
+            (unsafe { Py_True() }, true),
+            (unsafe { Py_False() }, false),
+            (unsafe { Py_None() }, false),
+        ] {
+            // MOV RCX, singleton
+            self.offset = write_mov_rcx(p_start, self.offset, singleton as u64);
+            // CMP RAX, RCX
+            self.offset = write_cmp_rax_rcx(p_start, self.offset);
+            // JNE next
+            self.offset = write_jne(p_start, self.offset, 0);
+            let next = self.offset;
+            if keeps && truth == jumps_if {
+                // JMP target
+                self.offset = write_jmp(p_start, self.offset, 0);
+                self.depth = depth;
+                self.jump_to_instruction(target)?;
+                self.depth = depth - 1;
+            } else if keeps {
+                // JMP pop
+                self.offset = write_jmp(p_start, self.offset, 0);
+                self.labels.refer(pop_label, self.offset);
+            } else {
+                // SUB [RAX], 1
+                self.offset = write_decref_rax_inline(p_start, self.offset);
+                // JMP destination
+                self.offset = write_jmp(p_start, self.offset, 0);
+                self.jump_to_instruction(if truth { if_true } else { if_false })?;
+            }
+            write_rel32(p_start, next - 4, (self.offset - next) as i32);
+        }
+        // MOV RDI, RAX
+        self.offset = write_mov_rax_to_rdi(p_start, self.offset);
+        if !keeps {
+            // MOV RAX, object_is_true
+            self.offset = write_mov_rax(p_start, self.offset, object_is_true as *const () as u64);
+            // CALL RAX
+            self.offset = write_call_rax(p_start, self.offset, self.depth);
+            // TEST RAX, RAX
+            self.offset = write_test_rax_rax(p_start, self.offset);
+            // JS error
+            self.offset = write_js(p_start, self.offset, 0);
+            self.labels.refer(self.error_label, self.offset);
+            // JNE if_true
+            self.offset = write_jne(p_start, self.offset, 0);
+            self.jump_to_instruction(if_true)?;
+            // JMP if_false
+            self.offset = write_jmp(p_start, self.offset, 0);
+            self.jump_to_instruction(if_false)?;
+            self.reachable = false;
+            return Some(());
+        }
+        // MOV RAX, object_truth
+        self.offset = write_mov_rax(p_start, self.offset, object_truth as *const () as u64);
+        // CALL RAX
+        self.offset = write_call_rax(p_start, self.offset, depth);
+        // TEST RAX, RAX
+        self.offset = write_test_rax_rax(p_start, self.offset);
+        // JS error
+        self.offset = write_js(p_start, self.offset, 0);
+        self.labels.refer(self.error_label, self.offset);
+        // JNE target or JE target
+        self.offset = if jumps_if {
+            write_jne(p_start, self.offset, 0)
+        } else {
+            write_je(p_start, self.offset, 0)
+        };
+        self.depth = depth;
+        self.jump_to_instruction(target)?;
+        self.depth = depth - 1;
+        // POP RDI
+        self.offset = write_pop_rdi(p_start, self.offset);
+        // MOV RAX, decref
+        self.offset = write_mov_rax(p_start, self.offset, decref as *const () as u64);
+        // CALL RAX
+        self.offset = write_call_rax(p_start, self.offset, self.depth);
+        // JMP done
+        self.offset = write_jmp(p_start, self.offset, 0);
+        self.labels.refer(done_label, self.offset);
+        self.labels.bind(pop_label, self.offset);
+        // POP RAX
+        self.offset = write_pop_rax(p_start, self.offset);
+        // SUB [RAX], 1
+        self.offset = write_decref_rax_inline(p_start, self.offset);
+        self.labels.bind(done_label, self.offset);
+        Some(())
+    }
+
+    /// Replaces `LOAD_CONST None`, `IS_OP` and `POP_JUMP_IF_FALSE` or `POP_JUMP_IF_TRUE` of
+    /// CPython 3.10, i.e. a branch on `x is None` or `x is not None`, by `PopJumpIfNone` or
+    /// `PopJumpIfNotNone`. The load and the test become `Nop`.
+    fn fuse_none_jump(&mut self) {
+        for i in 0..self.code.len() {
+            let (code, target) = self.code[i];
+            if !matches!(code, Bytecode::PopJumpIfFalse | Bytecode::PopJumpIfTrue) {
+                continue;
+            }
+            let j = match self.prev_instruction(i) {
+                Some(j) if !self.jump_targets[j + 1..=i].contains(&true) => j,
+                _ => continue,
+            };
+            let k = match self.prev_instruction(j) {
+                Some(k) if !self.jump_targets[k + 1..=j].contains(&true) => k,
+                _ => continue,
+            };
+            let (Bytecode::IsOp, invert) = self.code[j] else {
+                continue;
+            };
+            let (Bytecode::LoadConst, none) = self.code[k] else {
+                continue;
+            };
+            if self.constant(none) != unsafe { Py_None() } {
+                continue;
+            }
+            // `x is None` jumps on None when tested for true, `x is not None` when for false.
+            let fused = if (code == Bytecode::PopJumpIfTrue) == (invert == 0) {
+                Bytecode::PopJumpIfNone
+            } else {
+                Bytecode::PopJumpIfNotNone
+            };
+            self.code[k] = (Bytecode::Nop, 0);
+            self.code[j] = (Bytecode::Nop, 0);
+            self.code[i] = (fused, target);
+        }
+    }
+
+    fn prev_instruction(&self, i: usize) -> Option<usize> {
+        (0..i).rev().find(|j| self.code[*j].0 != Bytecode::Nop)
+    }
+
+    /// Folds operations on constants and branches on constants. Folded instructions become `Nop`
+    /// and a branch whose direction is known becomes `Jump` or `Nop`, so code which is only
+    /// reachable through the other direction is never emitted.
+    fn fold_constants(&mut self) {
+        for i in 0..self.code.len() {
+            let (code, arg) = self.code[i];
+            let n_operands = match code {
+                Bytecode::BinaryAdd
+                | Bytecode::BinarySubtract
+                | Bytecode::BinaryOp
+                | Bytecode::CompareOp => 2,
+                Bytecode::PopJumpIfFalse | Bytecode::PopJumpIfTrue => 1,
+                _ => continue,
+            };
+            let mut operands = Vec::new();
+            let mut j = i;
+            for _ in 0..n_operands {
+                match self.prev_instruction(j) {
+                    Some(k) if !self.jump_targets[k + 1..=j].contains(&true) => j = k,
+                    _ => break,
+                }
+                match self.const_source(j) {
+                    Some(source) => operands.insert(0, (j, source)),
+                    None => break,
+                }
+            }
+            if operands.len() != n_operands {
+                continue;
+            }
+
+            let folded = match code {
+                Bytecode::PopJumpIfFalse | Bytecode::PopJumpIfTrue => {
+                    let truth = unsafe { PyObject_IsTrue(operands[0].1 .0) };
+                    if truth < 0 {
+                        unsafe { PyErr_Clear() };
+                        continue;
+                    }
+                    let jumps = (truth == 1) == (code == Bytecode::PopJumpIfTrue);
+                    if jumps {
+                        (Bytecode::Jump, jump_target(i, &code, arg).unwrap() as u32)
+                    } else {
+                        (Bytecode::Nop, 0)
+                    }
+                }
+                _ => {
+                    let (a, b) = (operands[0].1 .0, operands[1].1 .0);
+                    let r = match fold_binary(&code, arg, a, b) {
+                        Some(r) => r,
+                        None => continue,
+                    };
+                    let n_consts =
+                        unsafe { PyTuple_Size((*(*self.frame).f_code).co_consts) } as usize;
+                    self.folded_consts.push(r);
+                    let index = n_consts + self.folded_consts.len() - 1;
+                    (Bytecode::LoadConst, index as u32)
+                }
+            };
+            info!("Fold {:?} into {:?}", self.code[i], folded);
+            // The guards run before the first operand, where the stack is as deep as in the
+            // interpreter when it resumes there.
+            let first = operands[0].0;
+            let globals = unsafe { (*self.frame).f_globals };
+            for (j, (value, name)) in operands {
+                self.code[j] = (Bytecode::Nop, 0);
+                if let Some(name) = name {
+                    self.global_guards[first].push(GlobalGuard::new(globals, name, value));
+                }
+            }
+            self.code[i] = folded;
+        }
+    }
+
+    fn compile_instruction(&mut self, i: usize) -> Option<()> {
+        let p_start = self.buf;
+        let (code, arg) = self.code[i];
+        match code {
+            Bytecode::ExtendedArg | Bytecode::Nop => {}
+            Bytecode::Jump | Bytecode::JumpForward => {
+                // JMP
+                self.offset = write_jmp(p_start, self.offset, 0);
+                self.jump_to_instruction(jump_target(i, &code, arg)?)?;
+                self.reachable = false;
+            }
+            Bytecode::UnaryNegative | Bytecode::UnaryInvert | Bytecode::UnaryNot => {
+                // POP RDI
+                self.offset = write_pop_rdi(p_start, self.offset);
+                // MOV ESI, op
+                self.offset = write_mov_esi(p_start, self.offset, code as u32);
+                // MOV RAX, unary_op
+                self.offset = write_mov_rax(p_start, self.offset, unary_op as *const () as u64);
+                // CALL RAX
+                self.offset = write_call_rax(p_start, self.offset, self.depth.checked_sub(1)?);
+                self.check_error();
+                // PUSH RAX
+                self.offset = write_push_rax(p_start, self.offset);
+            }
+            Bytecode::IsOp => {
+                self.compile_is_op(arg)?;
+                self.offset = write_box_bool(p_start, self.offset);
+                // PUSH RAX
+                self.offset = write_push_rax(p_start, self.offset);
+                self.depth = self.depth.checked_sub(1)?;
+            }
+            Bytecode::ContainsOp => {
+                // POP RSI
+                self.offset = write_pop_rsi(p_start, self.offset);
+                // POP RDI
+                self.offset = write_pop_rdi(p_start, self.offset);
+                // MOV EDX, invert
+                self.offset = write_mov_edx(p_start, self.offset, arg);
+                // MOV RAX, contains_op
+                self.offset = write_mov_rax(p_start, self.offset, contains_op as *const () as u64);
+                // CALL RAX
+                self.offset = write_call_rax(p_start, self.offset, self.depth.checked_sub(2)?);
+                self.check_error();
+                // PUSH RAX
+                self.offset = write_push_rax(p_start, self.offset);
+                self.depth = self.depth.checked_sub(1)?;
+            }
+            Bytecode::PopTop => {
+                // POP RDI
+                self.offset = write_pop_rdi(p_start, self.offset);
+                // DECREF RDI
+                self.offset = write_decref_rdi(p_start, self.offset, self.depth.checked_sub(1)?);
+                self.depth = self.depth.checked_sub(1)?;
+            }
+            Bytecode::Copy | Bytecode::DupTopTwo => {
+                let (n, times) = match code {
+                    Bytecode::Copy => (arg, 1),
+                    _ => (2, 2),
+                };
+                if n == 0 || n as usize > self.depth {
+                    return None;
+                }
+                for _ in 0..times {
+                    // MOV RAX, [RSP+8*(n-1)]
+                    self.offset = write_load_stack(p_start, self.offset, RAX, n - 1);
+                    // INCREF RAX
+                    self.offset = write_incref_rax(p_start, self.offset);
+                    // PUSH RAX
+                    self.offset = write_push_rax(p_start, self.offset);
+                    self.depth += 1;
+                }
+            }
+            Bytecode::Swap => {
+                if arg < 2 || arg as usize > self.depth {
+                    return None;
+                }
+                // MOV RAX, [RSP]
+                self.offset = write_load_stack(p_start, self.offset, RAX, 0);
+                // MOV RCX, [RSP+8*(n-1)]
+                self.offset = write_load_stack(p_start, self.offset, RCX, arg - 1);
+                // MOV [RSP], RCX
+                self.offset = write_store_stack(p_start, self.offset, RCX, 0);
+                // MOV [RSP+8*(n-1)], RAX
+                self.offset = write_store_stack(p_start, self.offset, RAX, arg - 1);
+            }
+            Bytecode::RotN => {
+                // Each value moves up by one and the top value moves below them.
+                const MAX_ROT: u32 = 16;
+                if !(2..=MAX_ROT).contains(&arg) || arg as usize > self.depth {
+                    return None;
+                }
+                // MOV RAX, [RSP]
+                self.offset = write_load_stack(p_start, self.offset, RAX, 0);
+                for k in 0..arg - 1 {
+                    // MOV RCX, [RSP+8*(k+1)]
+                    self.offset = write_load_stack(p_start, self.offset, RCX, k + 1);
+                    // MOV [RSP+8*k], RCX
+                    self.offset = write_store_stack(p_start, self.offset, RCX, k);
+                }
+                // MOV [RSP+8*(n-1)], RAX
+                self.offset = write_store_stack(p_start, self.offset, RAX, arg - 1);
+            }
+            Bytecode::LoadFast => {
+                self.compile_load_local(arg);
+                // INCREF RAX
+                self.offset = write_incref_rax(p_start, self.offset);
+                // PUSH RAX
+                self.offset = write_push_rax(p_start, self.offset);
+                self.depth += 1;
+            }
+            Bytecode::StoreFast => {
+                // POP RCX
+                self.offset = write_pop_rcx(p_start, self.offset);
+                // MOV RAX, &local
+                self.offset = write_mov_rax(p_start, self.offset, self.local_addr(arg) as u64);
+                // MOV RDI, [RAX]
+                // MOV [RAX], RCX
+                self.offset = write_swap_rcx_into_deref_rax(p_start, self.offset);
+                // XDECREF RDI
+                self.offset = write_xdecref_rdi(p_start, self.offset, self.depth.checked_sub(1)?);
+                self.depth = self.depth.checked_sub(1)?;
+            }
+            Bytecode::DeleteFast => {
+                self.compile_load_local(arg);
+                // MOV RDI, RAX
+                self.offset = write_mov_rax_to_rdi(p_start, self.offset);
+                // XOR ECX, ECX
+                self.offset = write_bytes(p_start, self.offset, &[0x31, 0xc9]);
+                // MOV RAX, &local
+                self.offset = write_mov_rax(p_start, self.offset, self.local_addr(arg) as u64);
+                // MOV [RAX], RCX
+                self.offset = write_bytes(p_start, self.offset, &[0x48, 0x89, 0x08]);
+                // DECREF RDI
+                self.offset = write_decref_rdi(p_start, self.offset, self.depth);
+            }
+            Bytecode::LoadClosure => {
+                let cell = self.cell(arg)?;
+                // MOV RAX, cell
+                self.offset = write_mov_rax(p_start, self.offset, cell as u64);
+                // INCREF RAX
+                self.offset = write_incref_rax(p_start, self.offset);
+                // PUSH RAX
+                self.offset = write_push_rax(p_start, self.offset);
+                self.depth += 1;
+            }
+            Bytecode::LoadDeref => {
+                self.compile_load_deref(arg)?;
+                // INCREF RAX
+                self.offset = write_incref_rax(p_start, self.offset);
+                // PUSH RAX
+                self.offset = write_push_rax(p_start, self.offset);
+                self.depth += 1;
+            }
+            Bytecode::LoadClassDeref => {
+                self.cell(arg)?;
+                // MOV RDI, frame
+                self.offset = write_mov_rdi(p_start, self.offset, self.frame as u64);
+                // MOV ESI, i
+                self.offset = write_mov_esi(p_start, self.offset, arg);
+                // MOV RAX, load_class_deref
+                self.offset =
+                    write_mov_rax(p_start, self.offset, load_class_deref as *const () as u64);
+                // CALL RAX
+                self.offset = write_call_rax(p_start, self.offset, self.depth);
+                self.check_error();
+                // PUSH RAX
+                self.offset = write_push_rax(p_start, self.offset);
+                self.depth += 1;
+            }
+            Bytecode::MakeFunction => {
+                let n_popped = 2 + (arg & 0xf).count_ones();
+                // MOV RDX, frame
+                self.offset = write_mov_rdx(p_start, self.offset, self.frame as u64);
+                self.compile_stack_call(make_function as *const () as u64, arg, n_popped, true)?
+            }
+            Bytecode::ImportName => {
+                // MOV RDX, frame
+                self.offset = write_mov_rdx(p_start, self.offset, self.frame as u64);
+                self.compile_stack_call(import_name as *const () as u64, arg, 2, true)?
+            }
+            Bytecode::ImportFrom => {
+                // MOV RDX, frame
+                self.offset = write_mov_rdx(p_start, self.offset, self.frame as u64);
+                self.compile_stack_call(import_from as *const () as u64, arg, 0, true)?
+            }
+            Bytecode::LoadBuildClass => {
+                // MOV RDI, frame
+                self.offset = write_mov_rdi(p_start, self.offset, self.frame as u64);
+                // MOV RAX, load_build_class
+                self.offset =
+                    write_mov_rax(p_start, self.offset, load_build_class as *const () as u64);
+                // CALL RAX
+                self.offset = write_call_rax(p_start, self.offset, self.depth);
+                self.check_error();
+                // PUSH RAX
+                self.offset = write_push_rax(p_start, self.offset);
+                self.depth += 1;
+            }
+            Bytecode::StoreDeref => {
+                let cell = self.cell(arg)?;
+                // POP RCX
+                self.offset = write_pop_rcx(p_start, self.offset);
+                // MOV RAX, &cell.ob_ref
+                self.offset = write_mov_rax(p_start, self.offset, unsafe {
+                    std::ptr::addr_of_mut!((*cell).ob_ref)
+                } as u64);
+                // MOV RDI, [RAX]
+                // MOV [RAX], RCX
+                self.offset = write_swap_rcx_into_deref_rax(p_start, self.offset);
+                // XDECREF RDI
+                self.offset = write_xdecref_rdi(p_start, self.offset, self.depth.checked_sub(1)?);
+                self.depth = self.depth.checked_sub(1)?;
+            }
+            Bytecode::DeleteDeref => {
+                let cell = self.cell(arg)?;
+                self.compile_load_deref(arg)?;
+                // MOV RDI, RAX
+                self.offset = write_mov_rax_to_rdi(p_start, self.offset);
+                // XOR ECX, ECX
+                self.offset = write_bytes(p_start, self.offset, &[0x31, 0xc9]);
+                // MOV RAX, &cell.ob_ref
+                self.offset = write_mov_rax(p_start, self.offset, unsafe {
+                    std::ptr::addr_of_mut!((*cell).ob_ref)
+                } as u64);
+                // MOV [RAX], RCX
+                self.offset = write_bytes(p_start, self.offset, &[0x48, 0x89, 0x08]);
+                // DECREF RDI
+                self.offset = write_decref_rdi(p_start, self.offset, self.depth);
+            }
+            Bytecode::UnpackSequence | Bytecode::UnpackEx => {
+                let n_targets = if code == Bytecode::UnpackSequence {
+                    arg as usize
+                } else {
+                    (arg & 0xff) as usize + 1 + (arg >> 8) as usize
+                };
+                let helper = if code == Bytecode::UnpackSequence {
+                    unpack_sequence as *const () as u64
+                } else {
+                    unpack_ex as *const () as u64
+                };
+                // POP RSI
+                self.offset = write_pop_rsi(p_start, self.offset);
+                self.depth = self.depth.checked_sub(1)? + n_targets;
+                // LEA RSP, [RBP-8*depth]
+                self.offset = write_reset_rsp(p_start, self.offset, self.depth);
+                // MOV RDI, RSP
+                self.offset = write_mov_rsp_to_rdi(p_start, self.offset);
+                // MOV EDX, arg
+                self.offset = write_mov_edx(p_start, self.offset, arg);
+                // MOV RAX, helper
+                self.offset = write_mov_rax(p_start, self.offset, helper);
+                // CALL RAX
+                self.offset = write_call_rax(p_start, self.offset, self.depth);
+                self.check_error_status();
+            }
+            Bytecode::ReturnValue | Bytecode::ReturnConst => {
+                if code == Bytecode::ReturnConst {
+                    // MOV RAX, const_object
+                    self.offset = write_mov_rax(p_start, self.offset, self.constant(arg) as u64);
+                    // INCREF RAX
+                    self.offset = write_incref_rax(p_start, self.offset);
+                    // PUSH RAX
+                    self.offset = write_push_rax(p_start, self.offset);
+                    self.depth += 1;
+                }
+                self.compile_finish_frame(FRAME_RETURNED, self.depth);
+                let n_below = self.depth.checked_sub(1)?;
+                if n_below == 0 {
+                    // POP RAX
+                    self.offset = write_pop_rax(p_start, self.offset);
+                } else {
+                    // MOV RDI, RSP
+                    self.offset = write_mov_rsp_to_rdi(p_start, self.offset);
+                    // MOV ESI, n_below
+                    self.offset = write_mov_esi(p_start, self.offset, n_below as u32);
+                    // MOV RAX, return_value
+                    self.offset =
+                        write_mov_rax(p_start, self.offset, return_value as *const () as u64);
+                    // CALL RAX
+                    self.offset = write_call_rax(p_start, self.offset, self.depth);
+                }
+                self.jump_to_epilogue();
+                self.depth = 0;
+                self.reachable = false;
+            }
+            Bytecode::BinaryAdd | Bytecode::BinarySubtract | Bytecode::BinaryOp => {
+                let op = match code {
+                    Bytecode::BinaryAdd => NB_ADD,
+                    Bytecode::BinarySubtract => NB_SUBTRACT,
+                    _ => arg,
+                };
+                if op > NB_INPLACE_XOR {
+                    return None;
+                }
+                // POP RSI
+                self.offset = write_pop_rsi(p_start, self.offset);
+                // POP RDI
+                self.offset = write_pop_rdi(p_start, self.offset);
+                // MOV EDX, op
+                self.offset = write_mov_edx(p_start, self.offset, op);
+                // MOV $RAX, binary_op
+                self.offset = write_mov_rax(p_start, self.offset, binary_op as *const () as u64);
+                // CALL $RAX
+                self.offset = write_call_rax(p_start, self.offset, self.depth.checked_sub(2)?);
+                self.check_error();
+                // PUSH RAX
+                self.offset = write_push_rax(p_start, self.offset);
+                self.depth = self.depth.checked_sub(1)?;
+            }
+            Bytecode::CompareOp => {
+                if arg > Py_GE as u32 {
+                    info!("Unknown compare op:{:?}", arg);
+                    return None;
+                }
+                // POP RSI
+                self.offset = write_pop_rsi(p_start, self.offset);
+                // POP RDI
+                self.offset = write_pop_rdi(p_start, self.offset);
+                // MOV EDX, op
+                self.offset = write_mov_edx(p_start, self.offset, arg);
+                // MOV $RAX, compare_op
+                self.offset = write_mov_rax(p_start, self.offset, compare_op as *const () as u64);
+                // CALL $RAX
+                self.offset = write_call_rax(p_start, self.offset, self.depth.checked_sub(2)?);
+                self.check_error();
+                // PUSH RAX
+                self.offset = write_push_rax(p_start, self.offset);
+                self.depth = self.depth.checked_sub(1)?;
+            }
+            Bytecode::LoadConst => {
+                let const_object = self.constant(arg);
+
+                // MOV RAX, const_object
+                self.offset = write_mov_rax(p_start, self.offset, const_object as u64);
+                // INCREF RAX
+                self.offset = write_incref_rax(p_start, self.offset);
+                // PUSH RAX
+                self.offset = write_push_rax(p_start, self.offset);
+                self.depth += 1;
+            }
+            Bytecode::PopJumpIfFalse
+            | Bytecode::PopJumpIfTrue
+            | Bytecode::JumpIfFalseOrPop
+            | Bytecode::JumpIfTrueOrPop => self.compile_truth_jump(i, code, arg)?,
+            Bytecode::PopJumpIfNone | Bytecode::PopJumpIfNotNone => {
+                self.depth = self.depth.checked_sub(1)?;
+                let target = jump_target(i, &code, arg)?;
+                let (if_none, if_not_none) = match code {
+                    Bytecode::PopJumpIfNone => (target, i + 1),
+                    _ => (i + 1, target),
+                };
+                // POP RAX
+                self.offset = write_pop_rax(p_start, self.offset);
+                // MOV RCX, Py_None
+                self.offset = write_mov_rcx(p_start, self.offset, unsafe { Py_None() } as u64);
+                // CMP RAX, RCX
+                self.offset = write_cmp_rax_rcx(p_start, self.offset);
+                // JNE not_none
+                self.offset = write_jne(p_start, self.offset, 0);
+                let not_none = self.offset;
+                // SUB [RAX], 1
+                self.offset = write_decref_rax_inline(p_start, self.offset);
+                // JMP if_none
+                self.offset = write_jmp(p_start, self.offset, 0);
+                self.jump_to_instruction(if_none)?;
+                write_rel32(p_start, not_none - 4, (self.offset - not_none) as i32);
+                // MOV RDI, RAX
+                self.offset = write_mov_rax_to_rdi(p_start, self.offset);
+                // MOV RAX, decref
+                self.offset = write_mov_rax(p_start, self.offset, decref as *const () as u64);
+                // CALL RAX
+                self.offset = write_call_rax(p_start, self.offset, self.depth);
+                // JMP if_not_none
+                self.offset = write_jmp(p_start, self.offset, 0);
+                self.jump_to_instruction(if_not_none)?;
+                self.reachable = false;
+            }
+            Bytecode::LoadGlobal => self.compile_load_global(arg),
+            Bytecode::LoadName => {
+                // MOV RDI, frame
+                self.offset = write_mov_rdi(p_start, self.offset, self.frame as u64);
+                // MOV RSI, name
+                self.offset = write_mov_rsi(p_start, self.offset, self.name(arg) as u64);
+                // MOV RAX, load_name
+                self.offset = write_mov_rax(p_start, self.offset, load_name as *const () as u64);
+                // CALL RAX
+                self.offset = write_call_rax(p_start, self.offset, self.depth);
+                self.check_error();
+                // PUSH RAX
+                self.offset = write_push_rax(p_start, self.offset);
+                self.depth += 1;
+            }
+            Bytecode::SetupAnnotations => {
+                // MOV RDI, frame
+                self.offset = write_mov_rdi(p_start, self.offset, self.frame as u64);
+                // MOV RAX, setup_annotations
+                self.offset =
+                    write_mov_rax(p_start, self.offset, setup_annotations as *const () as u64);
+                // CALL RAX
+                self.offset = write_call_rax(p_start, self.offset, self.depth);
+                self.check_error_status();
+            }
+            Bytecode::StoreName
+            | Bytecode::DeleteName
+            | Bytecode::StoreGlobal
+            | Bytecode::DeleteGlobal => {
+                let (helper, stores) = match code {
+                    Bytecode::StoreName => (store_name as *const () as u64, true),
+                    Bytecode::DeleteName => (delete_name as *const () as u64, false),
+                    Bytecode::StoreGlobal => (store_global as *const () as u64, true),
+                    _ => (delete_global as *const () as u64, false),
+                };
+                if stores {
+                    // POP RDX
+                    self.offset = write_pop_rdx(p_start, self.offset);
+                    self.depth = self.depth.checked_sub(1)?;
+                }
+                // MOV RDI, frame
+                self.offset = write_mov_rdi(p_start, self.offset, self.frame as u64);
+                // MOV RSI, name
+                self.offset = write_mov_rsi(p_start, self.offset, self.name(arg) as u64);
+                // MOV RAX, helper
+                self.offset = write_mov_rax(p_start, self.offset, helper);
+                // CALL RAX
+                self.offset = write_call_rax(p_start, self.offset, self.depth);
+                self.check_error_status();
+            }
+            Bytecode::LoadAttr => {
+                let cache = self.attr_cache(i)?;
+                // POP RDI
+                self.offset = write_pop_rdi(p_start, self.offset);
+                // MOV RSI, name
+                self.offset = write_mov_rsi(p_start, self.offset, self.name(arg) as u64);
+                // MOV RDX, cache
+                self.offset = write_mov_rdx(p_start, self.offset, cache as u64);
+                // MOV RAX, load_attr
+                self.offset = write_mov_rax(p_start, self.offset, load_attr as *const () as u64);
+                // CALL RAX
+                self.offset = write_call_rax(p_start, self.offset, self.depth.checked_sub(1)?);
+                self.check_error();
+                // PUSH RAX
+                self.offset = write_push_rax(p_start, self.offset);
+            }
+            Bytecode::StoreAttr | Bytecode::DeleteAttr => {
+                let cache = self.attr_cache(i)?;
+                // POP RDI
+                self.offset = write_pop_rdi(p_start, self.offset);
+                if code == Bytecode::StoreAttr {
+                    // POP RSI
+                    self.offset = write_pop_rsi(p_start, self.offset);
+                    self.depth = self.depth.checked_sub(2)?;
+                } else {
+                    // MOV RSI, 0
+                    self.offset = write_mov_rsi(p_start, self.offset, 0);
+                    self.depth = self.depth.checked_sub(1)?;
+                }
+                // MOV RDX, name
+                self.offset = write_mov_rdx(p_start, self.offset, self.name(arg) as u64);
+                // MOV RCX, cache
+                self.offset = write_mov_rcx(p_start, self.offset, cache as u64);
+                // MOV RAX, store_attr
+                self.offset = write_mov_rax(p_start, self.offset, store_attr as *const () as u64);
+                // CALL RAX
+                self.offset = write_call_rax(p_start, self.offset, self.depth);
+                self.check_error_status();
+            }
+            Bytecode::LoadZeroSuperAttr | Bytecode::LoadZeroSuperMethod => {
+                let method = code == Bytecode::LoadZeroSuperMethod;
+                // PUSH 0 (class)
+                self.offset = write_push_null(p_start, self.offset);
+                // PUSH 0 (obj)
+                self.offset = write_push_null(p_start, self.offset);
+                self.depth += 2;
+                let cache = self.attr_cache(i)?;
+                // MOV RDX, cache
+                self.offset = write_mov_rdx(p_start, self.offset, cache as u64);
+                // MOV RCX, frame
+                self.offset = write_mov_rcx(p_start, self.offset, self.frame as u64);
+                let n_popped = if method { 1 } else { 2 };
+                self.compile_stack_call(
+                    load_super_attr as *const () as u64,
+                    method as u32,
+                    n_popped,
+                    false,
+                )?
+            }
+            Bytecode::LoadMethod => {
+                let cache = self.attr_cache(i)?;
+                // PUSH 0
+                self.offset = write_push_null(p_start, self.offset);
+                self.depth += 1;
+                // MOV RDI, RSP
+                self.offset = write_mov_rsp_to_rdi(p_start, self.offset);
+                // MOV RSI, name
+                self.offset = write_mov_rsi(p_start, self.offset, self.name(arg) as u64);
+                // MOV RDX, cache
+                self.offset = write_mov_rdx(p_start, self.offset, cache as u64);
+                // MOV RAX, load_method
+                self.offset = write_mov_rax(p_start, self.offset, load_method as *const () as u64);
+                // CALL RAX
+                self.offset = write_call_rax(p_start, self.offset, self.depth);
+                self.check_error_status();
+            }
+            Bytecode::FormatValue => {
+                let n_popped = if arg as u64 & FVS_HAVE_SPEC != 0 {
+                    2
+                } else {
+                    1
+                };
+                self.compile_format(format_value as *const () as u64, arg, n_popped, arg == 0)?
+            }
+            Bytecode::CallIntrinsic1 | Bytecode::ImportStar | Bytecode::PrintExpr => {
+                let intrinsic = match code {
+                    Bytecode::ImportStar => INTRINSIC_IMPORT_STAR,
+                    Bytecode::PrintExpr => INTRINSIC_PRINT,
+                    _ => arg,
+                };
+                INTRINSICS_1.get(intrinsic as usize).copied().flatten()?;
+                // MOV RDX, frame
+                self.offset = write_mov_rdx(p_start, self.offset, self.frame as u64);
+                self.compile_stack_call(call_intrinsic_1 as *const () as u64, intrinsic, 1, true)?;
+                if code != Bytecode::CallIntrinsic1 {
+                    // IMPORT_STAR and PRINT_EXPR of 3.10 push nothing.
+                    // POP RDI
+                    self.offset = write_pop_rdi(p_start, self.offset);
+                    // DECREF RDI
+                    self.offset =
+                        write_decref_rdi(p_start, self.offset, self.depth.checked_sub(1)?);
+                    self.depth -= 1;
+                }
+            }
+            Bytecode::GetLen => {
+                self.compile_stack_call(get_len as *const () as u64, arg, 0, true)?
+            }
+            Bytecode::MatchMapping | Bytecode::MatchSequence => {
+                let flag = if code == Bytecode::MatchMapping {
+                    Py_TPFLAGS_MAPPING
+                } else {
+                    Py_TPFLAGS_SEQUENCE
+                };
+                // MOV RAX, [RSP]
+                self.offset = write_load_rax_top(p_start, self.offset);
+                // MOV RAX, [RAX+8]
+                self.offset = write_load_ob_type_rax(p_start, self.offset);
+                // TEST [RAX+tp_flags], flag
+                self.offset = write_test_tp_flags_rax(p_start, self.offset, flag as u32);
+                // SETNE AL
+                self.offset = write_setcc_al(p_start, self.offset, CC_NE);
+                self.offset = write_box_bool(p_start, self.offset);
+                // PUSH RAX
+                self.offset = write_push_rax(p_start, self.offset);
+                self.depth += 1;
+            }
+            Bytecode::MatchKeys => {
+                self.compile_stack_call(match_keys as *const () as u64, arg, 0, true)?;
+                // MOV RAX, [RSP]
+                self.offset = write_load_rax_top(p_start, self.offset);
+                // MOV RCX, Py_None
+                self.offset = write_mov_rcx(p_start, self.offset, unsafe { Py_None() } as u64);
+                // CMP RAX, RCX
+                self.offset = write_cmp_rax_rcx(p_start, self.offset);
+                // SETNE AL
+                self.offset = write_setcc_al(p_start, self.offset, CC_NE);
+                self.offset = write_box_bool(p_start, self.offset);
+                // PUSH RAX
+                self.offset = write_push_rax(p_start, self.offset);
+                self.depth += 1;
+            }
+            Bytecode::CopyDictWithoutKeys => {
+                self.compile_stack_call(copy_dict_without_keys as *const () as u64, arg, 1, true)?
+            }
+            Bytecode::MatchClass => {
+                self.compile_stack_call(match_class as *const () as u64, arg, 2, true)?
+            }
+            Bytecode::BuildString => {
+                self.compile_stack_call(build_string as *const () as u64, arg, arg, true)?
+            }
+            Bytecode::BuildTuple => {
+                self.compile_stack_call(build_tuple as *const () as u64, arg, arg, true)?
+            }
+            Bytecode::BuildList => {
+                self.compile_stack_call(build_list as *const () as u64, arg, arg, true)?
+            }
+            Bytecode::BuildSet => {
+                self.compile_stack_call(build_set as *const () as u64, arg, arg, true)?
+            }
+            Bytecode::BuildMap => {
+                self.compile_stack_call(build_map as *const () as u64, arg, 2 * arg, true)?
+            }
+            Bytecode::BuildConstKeyMap => self.compile_stack_call(
+                build_const_key_map as *const () as u64,
+                arg,
+                arg + 1,
+                true,
+            )?,
+            Bytecode::ListAppend => {
+                self.compile_stack_call(list_append as *const () as u64, arg, 1, false)?
+            }
+            Bytecode::SetAdd => {
+                self.compile_stack_call(set_add as *const () as u64, arg, 1, false)?
+            }
+            Bytecode::ListExtend => {
+                self.compile_stack_call(list_extend as *const () as u64, arg, 1, false)?
+            }
+            Bytecode::SetUpdate => {
+                self.compile_stack_call(set_update as *const () as u64, arg, 1, false)?
+            }
+            Bytecode::DictUpdate => {
+                self.compile_stack_call(dict_update as *const () as u64, arg, 1, false)?
+            }
+            Bytecode::DictMerge => {
+                self.compile_stack_call(dict_merge as *const () as u64, arg, 1, false)?
+            }
+            Bytecode::MapAdd => {
+                self.compile_stack_call(map_add as *const () as u64, arg, 2, false)?
+            }
+            Bytecode::BinarySubscr => self.compile_binary_subscr()?,
+            Bytecode::StoreSubscr | Bytecode::DeleteSubscr => {
+                // POP RSI
+                self.offset = write_pop_rsi(p_start, self.offset);
+                // POP RDI
+                self.offset = write_pop_rdi(p_start, self.offset);
+                if code == Bytecode::StoreSubscr {
+                    // POP RAX
+                    self.offset = write_pop_rax(p_start, self.offset);
+                    self.depth = self.depth.checked_sub(3)?;
+                } else {
+                    // XOR EAX, EAX
+                    self.offset = write_zero_rax(p_start, self.offset);
+                    self.depth = self.depth.checked_sub(2)?;
+                }
+                // MOV RDX, RSI
+                self.offset = write_bytes(p_start, self.offset, &[0x48, 0x89, 0xf2]);
+                // MOV RSI, RDI
+                self.offset = write_bytes(p_start, self.offset, &[0x48, 0x89, 0xfe]);
+                // MOV RDI, RAX
+                self.offset = write_mov_rax_to_rdi(p_start, self.offset);
+                // MOV RAX, store_subscr
+                self.offset = write_mov_rax(p_start, self.offset, store_subscr as *const () as u64);
+                // CALL RAX
+                self.offset = write_call_rax(p_start, self.offset, self.depth);
+                self.check_error_status();
+            }
+            Bytecode::BuildSlice => {
+                self.compile_stack_call(build_slice as *const () as u64, arg, arg, true)?
+            }
+            Bytecode::CallFunction | Bytecode::Call => {
+                let method = code == Bytecode::Call;
+                let n_values = arg as usize + if method { 2 } else { 1 };
+                // MOV RDI, RSP
+                self.offset = write_mov_rsp_to_rdi(p_start, self.offset);
+                // MOV ESI, n
+                self.offset = write_mov_esi(p_start, self.offset, arg);
+                // MOV EDX, method
+                self.offset = write_mov_edx(p_start, self.offset, method as u32);
+                // MOV RAX, call
+                self.offset = write_mov_rax(p_start, self.offset, call as *const () as u64);
+                // CALL RAX
+                self.offset = write_call_rax(p_start, self.offset, self.depth);
+                self.depth = self.depth.checked_sub(n_values)?;
+                // LEA RSP, [RBP-8*depth]
+                self.offset = write_reset_rsp(p_start, self.offset, self.depth);
+                self.check_error();
+                // PUSH RAX
+                self.offset = write_push_rax(p_start, self.offset);
+                self.depth += 1;
+            }
+            Bytecode::SetupFinally => {
+                let handler = jump_target(i, &code, arg)?;
+                let level = self.depth;
+                let mut handler_blocks = self.blocks.clone();
+                handler_blocks.push(Block::ExceptHandler { level });
+                self.enter_instruction(handler, level + 6, handler_blocks)?;
+                self.blocks.push(Block::Finally { handler, level });
+            }
+            Bytecode::YieldValue => {
+                let depth = self.depth.checked_sub(1)?;
+                self.compile_suspend(depth, i);
+                // The value sent to the generator replaces the yielded one.
+                self.enter_instruction(i + 1, self.depth, self.blocks.clone())?;
+                self.reachable = false;
+            }
+            Bytecode::YieldFrom => {
+                let returned_label = self.labels.new_label();
+                // MOV RDI, RSP
+                self.offset = write_mov_rsp_to_rdi(p_start, self.offset);
+                // MOV RAX, yield_from
+                self.offset = write_mov_rax(p_start, self.offset, yield_from as *const () as u64);
+                // CALL RAX
+                self.offset = write_call_rax(p_start, self.offset, self.depth);
+                self.check_error_status();
+                // JE returned
+                self.offset = write_je(p_start, self.offset, 0);
+                self.labels.refer(returned_label, self.offset);
+                // Resumes at this instruction with the iterator and the value sent.
+                self.compile_suspend(self.depth.checked_sub(1)?, i.checked_sub(1)?);
+                self.labels.bind(returned_label, self.offset);
+                self.depth = self.depth.checked_sub(1)?;
+                // LEA RSP, [RBP-8*depth]
+                self.offset = write_reset_rsp(p_start, self.offset, self.depth);
+            }
+            Bytecode::GetYieldFromIter | Bytecode::GetAwaitable | Bytecode::GetIter => {
+                let (helper, arg) = match code {
+                    Bytecode::GetYieldFromIter => {
+                        (get_yield_from_iter as *const () as u64, unsafe {
+                            (*(*self.frame).f_code).co_flags as u32
+                        })
+                    }
+                    Bytecode::GetAwaitable => (get_awaitable as *const () as u64, 0),
+                    _ => (get_iter as *const () as u64, 0),
+                };
+                // POP RDI
+                self.offset = write_pop_rdi(p_start, self.offset);
+                // MOV ESI, arg
+                self.offset = write_mov_esi(p_start, self.offset, arg);
+                // MOV RAX, helper
+                self.offset = write_mov_rax(p_start, self.offset, helper);
+                // CALL RAX
+                self.offset = write_call_rax(p_start, self.offset, self.depth.checked_sub(1)?);
+                self.check_error();
+                // PUSH RAX
+                self.offset = write_push_rax(p_start, self.offset);
+            }
+            Bytecode::ForIter => {
+                let next_label = self.labels.new_label();
+                // PUSH 0
+                self.offset = write_push_null(p_start, self.offset);
+                self.depth += 1;
+                // MOV RDI, RSP
+                self.offset = write_mov_rsp_to_rdi(p_start, self.offset);
+                // MOV RAX, for_iter
+                self.offset = write_mov_rax(p_start, self.offset, for_iter as *const () as u64);
+                // CALL RAX
+                self.offset = write_call_rax(p_start, self.offset, self.depth);
+                self.check_error_status();
+                // JNE next
+                self.offset = write_jne(p_start, self.offset, 0);
+                self.labels.refer(next_label, self.offset);
+                // POP RAX
+                self.offset = write_pop_rax(p_start, self.offset);
+                // POP RDI
+                self.offset = write_pop_rdi(p_start, self.offset);
+                // DECREF RDI
+                self.offset = write_decref_rdi(p_start, self.offset, self.depth.checked_sub(2)?);
+                self.depth -= 2;
+                // JMP exhausted
+                self.offset = write_jmp(p_start, self.offset, 0);
+                self.jump_to_instruction(jump_target(i, &code, arg)?)?;
+                self.depth += 2;
+                self.labels.bind(next_label, self.offset);
+            }
+            Bytecode::SetupWith => {
+                let handler = jump_target(i, &code, arg)?;
+                // MOV RDI, RSP
+                self.offset = write_mov_rsp_to_rdi(p_start, self.offset);
+                // MOV RAX, setup_with
+                self.offset = write_mov_rax(p_start, self.offset, setup_with as *const () as u64);
+                // CALL RAX
+                self.offset = write_call_rax(p_start, self.offset, self.depth);
+                self.check_error();
+                // PUSH RAX
+                self.offset = write_push_rax(p_start, self.offset);
+                // The block is below the result of __enter__, so that an exception leaves
+                // __exit__ for the handler.
+                let level = self.depth;
+                let mut handler_blocks = self.blocks.clone();
+                handler_blocks.push(Block::ExceptHandler { level });
+                self.enter_instruction(handler, level + 6, handler_blocks)?;
+                self.blocks.push(Block::Finally { handler, level });
+                self.depth += 1;
+            }
+            Bytecode::WithExceptStart => {
+                self.compile_stack_call(with_except_start as *const () as u64, arg, 0, true)?
+            }
+            Bytecode::PopBlock => match self.blocks.pop() {
+                Some(Block::Finally { .. }) => {}
+                _ => return None,
+            },
+            Bytecode::PopExcept => {
+                match self.blocks.pop() {
+                    Some(Block::ExceptHandler { level }) if self.depth == level + 3 => {}
+                    _ => return None,
+                }
+                self.compile_pop_exception(pop_except as *const () as u64)?;
+            }
+            Bytecode::Reraise => {
+                self.compile_pop_exception(reraise as *const () as u64)?;
+                let (_, reraise) = self.unwind_labels(&self.blocks.clone());
+                // JMP reraise
+                self.offset = write_jmp(p_start, self.offset, 0);
+                self.labels.refer(reraise, self.offset);
+                self.reachable = false;
+            }
+            Bytecode::RaiseVarargs => {
+                if arg > 2 {
+                    return None;
+                }
+                // MOV RDI, RSP
+                self.offset = write_mov_rsp_to_rdi(p_start, self.offset);
+                // MOV ESI, n
+                self.offset = write_mov_esi(p_start, self.offset, arg);
+                // MOV RAX, raise_varargs
+                self.offset =
+                    write_mov_rax(p_start, self.offset, raise_varargs as *const () as u64);
+                // CALL RAX
+                self.offset = write_call_rax(p_start, self.offset, self.depth);
+                self.depth = self.depth.checked_sub(arg as usize)?;
+                // LEA RSP, [RBP-8*depth]
+                self.offset = write_reset_rsp(p_start, self.offset, self.depth);
+                let (_, reraise) = self.unwind_labels(&self.blocks.clone());
+                // TEST RAX, RAX
+                self.offset = write_test_rax_rax(p_start, self.offset);
+                // JNE reraise
+                self.offset = write_jne(p_start, self.offset, 0);
+                self.labels.refer(reraise, self.offset);
+                // JMP error
+                self.offset = write_jmp(p_start, self.offset, 0);
+                self.labels.refer(self.error_label, self.offset);
+                self.reachable = false;
+            }
+            Bytecode::JumpIfNotExcMatch => {
+                // POP RSI
+                self.offset = write_pop_rsi(p_start, self.offset);
+                // POP RDI
+                self.offset = write_pop_rdi(p_start, self.offset);
+                self.depth = self.depth.checked_sub(2)?;
+                // MOV RAX, exc_match
+                self.offset = write_mov_rax(p_start, self.offset, exc_match as *const () as u64);
+                // CALL RAX
+                self.offset = write_call_rax(p_start, self.offset, self.depth);
+                self.check_error_status();
+                // JE target
+                self.offset = write_je(p_start, self.offset, 0);
+                self.jump_to_instruction(jump_target(i, &code, arg)?)?;
+            }
+            _ => {
+                info!("Unknown code:{:?}", code);
+                info!("Fallback to the Python interpreter");
+                return None;
+            }
+        }
+        Some(())
+    }
+
+    /// Returns how a leaf of an arithmetic region is unboxed, based on the value it has while
+    /// compiling. The value is guarded again when the compiled code runs.
+    fn leaf_kind(&self, code: &Bytecode, arg: u32) -> Option<Unboxed> {
+        let (value, is_const) = match code {
+            Bytecode::LoadFast => (self.local(arg), false),
+            Bytecode::LoadConst => (self.constant(arg), true),
+            _ => return None,
+        };
+        if value.is_null() {
+            return None;
+        }
+        unsafe {
+            if Py_TYPE(value) == std::ptr::addr_of_mut!(PyFloat_Type) {
+                return Some(Unboxed::Float);
+            }
+            if Py_TYPE(value) != std::ptr::addr_of_mut!(PyLong_Type) {
+                return None;
+            }
+            if is_const {
+                let mut overflow = 0;
+                PyLong_AsLongAndOverflow(value, &mut overflow);
+                (overflow == 0).then_some(Unboxed::Int)
+            } else {
+                let size = (*(value as *mut PyVarObject)).ob_size;
+                (-1..=1).contains(&size).then_some(Unboxed::Int)
+            }
+        }
+    }
+
+    fn find_arith_region(&self, start: usize) -> Option<ArithRegion> {
+        let mut kinds = Vec::new();
+        let mut n_ops = 0;
+        let mut region = None;
+        for i in start..self.code.len() {
+            if i != start && self.jump_targets[i] {
+                break;
+            }
+            let (code, arg) = (&self.code[i].0, self.code[i].1);
+            if let Some(k) = self.leaf_kind(code, arg) {
+                kinds.push(k);
+            } else if let (Some(op), 2..) = (arith_op(code, arg), kinds.len()) {
+                let b = kinds.pop().unwrap();
+                let a = kinds.pop().unwrap();
+                match arith_result(op, a, b) {
+                    Some(k) => kinds.push(k),
+                    None => break,
+                }
+                n_ops += 1;
+            } else if let (Some(op), 1..) = (unary_arith_op(code), kinds.len()) {
+                let a = kinds.pop().unwrap();
+                match unary_arith_result(op, a) {
+                    Some(k) => kinds.push(k),
+                    None => break,
+                }
+                n_ops += 1;
+            } else {
+                break;
+            }
+            if kinds.len() == 1 && n_ops > 0 {
+                region = Some(ArithRegion {
+                    end: i + 1,
+                    kind: kinds[0],
+                });
+            }
+            if kinds.contains(&Unboxed::Bool) {
+                break;
+            }
+        }
+        region
+    }
+
+    /// Emits a region twice. The fast path keeps the values unboxed and boxes only the result.
+    /// When a guard or an overflow check fails, it discards the unboxed values and runs the
+    /// generic path, which is safe because loads and arithmetic on numbers have no side effects.
+    fn compile_arith_region(&mut self, start: usize, region: &ArithRegion) -> Option<()> {
+        info!(
+            "Arithmetic region:{:?}..{:?} {:?}",
+            start, region.end, region.kind
+        );
+        let p_start = self.buf;
+        let slow_label = self.labels.new_label();
+        let done_label = self.labels.new_label();
+        let start_depth = self.depth;
+        let mut kinds = Vec::new();
+
+        for i in start..region.end {
+            let (code, arg) = self.code[i];
+            if let Some(k) = self.leaf_kind(&code, arg) {
+                self.compile_unboxed_leaf(&code, arg, k, slow_label);
+                kinds.push(k);
+            } else if let Some(op) = unary_arith_op(&code) {
+                let k = unary_arith_result(op, kinds.pop()?)?;
+                // POP RAX
+                self.offset = write_pop_rax(p_start, self.offset);
+                self.compile_unboxed_unary_op(op, k, slow_label);
+                kinds.push(k);
+            } else {
+                let op = arith_op(&code, arg)?;
+                let b = kinds.pop()?;
+                let a = kinds.pop()?;
+                let k = arith_result(op, a, b)?;
+                // POP RCX
+                self.offset = write_pop_rcx(p_start, self.offset);
+                // POP RAX
+                self.offset = write_pop_rax(p_start, self.offset);
+                self.compile_unboxed_op(op, a, b, k, slow_label);
+                kinds.push(k);
+            }
+            // PUSH RAX
+            self.offset = write_push_rax(p_start, self.offset);
+        }
+
+        // The result escapes, so box it.
+        match region.kind {
+            Unboxed::Int | Unboxed::Float => {
+                // POP RDI
+                self.offset = write_pop_rdi(p_start, self.offset);
+                let box_fn = match region.kind {
+                    Unboxed::Int => PyLong_FromLong as *const () as u64,
+                    _ => box_float as *const () as u64,
+                };
+                // MOV RAX, box_fn
+                self.offset = write_mov_rax(p_start, self.offset, box_fn);
+                // CALL RAX
+                self.offset = write_call_rax(p_start, self.offset, start_depth);
+                self.check_error();
+            }
+            Unboxed::Bool => {
+                // POP RAX
+                self.offset = write_pop_rax(p_start, self.offset);
+                self.offset = write_box_bool(p_start, self.offset);
+            }
+        }
+        // PUSH RAX
+        self.offset = write_push_rax(p_start, self.offset);
+        // JMP done
+        self.offset = write_jmp(p_start, self.offset, 0);
+        self.labels.refer(done_label, self.offset);
+
+        self.labels.bind(slow_label, self.offset);
+        // LEA RSP, [RBP-8*start_depth]
+        self.offset = write_reset_rsp(p_start, self.offset, start_depth);
+        self.depth = start_depth;
+        for i in start..region.end {
+            self.compile_instruction(i)?;
+        }
+        self.labels.bind(done_label, self.offset);
+        Some(())
+    }
+
+    /// Loads a leaf of an arithmetic region unboxed into RAX.
+    fn compile_unboxed_leaf(
+        &mut self,
+        code: &Bytecode,
+        arg: u32,
+        kind: Unboxed,
+        slow_label: usize,
+    ) {
+        let p_start = self.buf;
+        if *code == Bytecode::LoadConst {
+            let c = self.constant(arg);
+            let value = match kind {
+                Unboxed::Float => unsafe { PyFloat_AsDouble(c).to_bits() },
+                _ => unsafe { PyLong_AsLong(c) as u64 },
+            };
+            // MOV RAX, value
+            self.offset = write_mov_rax(p_start, self.offset, value);
+            return;
+        }
+
+        // MOV RAX, &l
+        self.offset = write_mov_rax(p_start, self.offset, self.local_addr(arg) as u64);
+        // MOV RAX, [RAX]
+        self.offset = write_deref_rax(p_start, self.offset);
+        if self.may_be_unbound(arg) {
+            // TEST RAX, RAX
+            self.offset = write_test_rax_rax(p_start, self.offset);
+            // JE slow
+            self.offset = write_je(p_start, self.offset, 0);
+            self.labels.refer(slow_label, self.offset);
+        }
+        let ty = match kind {
+            Unboxed::Float => std::ptr::addr_of_mut!(PyFloat_Type),
+            _ => std::ptr::addr_of_mut!(PyLong_Type),
+        };
+        // MOV RCX, ty
+        self.offset = write_mov_rcx(p_start, self.offset, ty as u64);
+        // CMP [RAX+8], RCX
+        self.offset = write_cmp_ob_type_rcx(p_start, self.offset);
+        // JNE slow
+        self.offset = write_jne(p_start, self.offset, 0);
+        self.labels.refer(slow_label, self.offset);
+        match kind {
+            Unboxed::Float => {
+                // MOV RAX, [RAX+16] (ob_fval)
+                self.offset = write_load_rax_field(p_start, self.offset, 16);
+            }
+            _ => {
+                let (offset, jump_end) = write_unbox_compact_long(p_start, self.offset);
+                self.offset = offset;
+                self.labels.refer(slow_label, jump_end);
+            }
+        }
+    }
+
+    /// Computes `<op> RAX` into RAX where the operand and the result are unboxed as `kind`.
+    fn compile_unboxed_unary_op(&mut self, op: UnaryArithOp, kind: Unboxed, slow_label: usize) {
+        let p_start = self.buf;
+        match (op, kind) {
+            (UnaryArithOp::Negative, Unboxed::Float) => {
+                // BTC RAX, 63
+                self.offset = write_bytes(p_start, self.offset, &[0x48, 0x0f, 0xba, 0xf8, 0x3f]);
+            }
+            (UnaryArithOp::Negative, _) => {
+                // NEG RAX
+                self.offset = write_bytes(p_start, self.offset, &[0x48, 0xf7, 0xd8]);
+                // JO slow
+                self.offset = write_jo(p_start, self.offset, 0);
+                self.labels.refer(slow_label, self.offset);
+            }
+            (UnaryArithOp::Invert, _) => {
+                // NOT RAX
+                self.offset = write_bytes(p_start, self.offset, &[0x48, 0xf7, 0xd0]);
+            }
+        }
+    }
+
+    /// Computes `RAX <op> RCX` into RAX where the operands are unboxed as `a` and `b` and the
+    /// result as `result`.
+    fn compile_unboxed_op(
+        &mut self,
+        op: ArithOp,
+        a: Unboxed,
+        b: Unboxed,
+        result: Unboxed,
+        slow_label: usize,
+    ) {
+        let p_start = self.buf;
+        if a == Unboxed::Int && b == Unboxed::Int && op != ArithOp::TrueDiv {
+            if let ArithOp::Compare(cmp) = op {
+                // CMP RAX, RCX
+                self.offset = write_cmp_rax_rcx(p_start, self.offset);
+                let cc = [CC_L, CC_LE, CC_E, CC_NE, CC_G, CC_GE][cmp as usize];
+                self.offset = write_setcc_al(p_start, self.offset, cc);
+                self.offset = write_movzx_eax_al(p_start, self.offset);
+                return;
+            }
+            self.offset = match op {
+                ArithOp::Add => write_add_rax_rcx(p_start, self.offset),
+                ArithOp::Sub => write_sub_rax_rcx(p_start, self.offset),
+                _ => write_imul_rax_rcx(p_start, self.offset),
+            };
+            // JO slow
+            self.offset = write_jo(p_start, self.offset, 0);
+            self.labels.refer(slow_label, self.offset);
+            return;
+        }
+
+        // Move the operands to XMM0 and XMM1, promoting ints which convert exactly.
+        if a == Unboxed::Int {
+            self.offset = write_check_exact_double(p_start, self.offset, false);
+            self.labels.refer(slow_label, self.offset);
+            self.offset = write_cvtsi2sd_xmm0_rax(p_start, self.offset);
+        } else {
+            self.offset = write_movq_xmm0_rax(p_start, self.offset);
+        }
+        if b == Unboxed::Int {
+            self.offset = write_check_exact_double(p_start, self.offset, true);
+            self.labels.refer(slow_label, self.offset);
+            self.offset = write_cvtsi2sd_xmm1_rcx(p_start, self.offset);
+        } else {
+            self.offset = write_movq_xmm1_rcx(p_start, self.offset);
+        }
+
+        match op {
+            ArithOp::Add => self.offset = write_addsd(p_start, self.offset),
+            ArithOp::Sub => self.offset = write_subsd(p_start, self.offset),
+            ArithOp::Mul => self.offset = write_mulsd(p_start, self.offset),
+            ArithOp::TrueDiv => {
+                // Let the generic path raise ZeroDivisionError.
+                self.offset = write_je_xmm1_zero(p_start, self.offset);
+                self.labels.refer(slow_label, self.offset);
+                self.offset = write_divsd(p_start, self.offset);
+            }
+            ArithOp::Compare(cmp) => {
+                // UCOMISD sets ZF, PF and CF when either operand is NaN, so that only != holds.
+                let cmp = cmp as c_int;
+                self.offset = if cmp == Py_LT || cmp == Py_LE {
+                    write_ucomisd_xmm1_xmm0(p_start, self.offset)
+                } else {
+                    write_ucomisd_xmm0_xmm1(p_start, self.offset)
+                };
+                if cmp == Py_LT || cmp == Py_GT {
+                    self.offset = write_setcc_al(p_start, self.offset, CC_A);
+                } else if cmp == Py_LE || cmp == Py_GE {
+                    self.offset = write_setcc_al(p_start, self.offset, CC_AE);
+                } else if cmp == Py_EQ {
+                    self.offset = write_setcc_al(p_start, self.offset, CC_E);
+                    self.offset = write_setcc_cl(p_start, self.offset, CC_NP);
+                    self.offset = write_and_al_cl(p_start, self.offset);
+                } else {
+                    self.offset = write_setcc_al(p_start, self.offset, CC_NE);
+                    self.offset = write_setcc_cl(p_start, self.offset, CC_P);
+                    self.offset = write_or_al_cl(p_start, self.offset);
+                }
+                self.offset = write_movzx_eax_al(p_start, self.offset);
+                return;
+            }
+        }
+        debug_assert_eq!(result, Unboxed::Float);
+        self.offset = write_movq_rax_xmm0(p_start, self.offset);
+    }
+}
+
+/// Returns the version tag of a dict, which changes whenever the dict is modified.
+fn dict_version(dict: *mut PyObject) -> u64 {
+    unsafe { (*(dict as *mut PyDictObject)).ma_version_tag }
+}
+
+/// Whether `value` is an immutable builtin whose operations can run while compiling.
+fn is_foldable(value: *mut PyObject) -> bool {
+    unsafe {
+        let ty = Py_TYPE(value);
+        value == Py_None()
+            || ty == std::ptr::addr_of_mut!(PyBool_Type)
+            || ty == std::ptr::addr_of_mut!(PyLong_Type)
+            || ty == std::ptr::addr_of_mut!(PyFloat_Type)
+            || ty == std::ptr::addr_of_mut!(PyUnicode_Type)
+    }
+}
+
+/// Computes a binary operation or a comparison on two constants. Operations which may raise or
+/// build large objects are left to run time.
+fn fold_binary(
+    code: &Bytecode,
+    arg: u32,
+    a: *mut PyObject,
+    b: *mut PyObject,
+) -> Option<*mut PyObject> {
+    if !is_foldable(a) || !is_foldable(b) {
+        return None;
+    }
+    let is_str = |o| unsafe { Py_TYPE(o) == std::ptr::addr_of_mut!(PyUnicode_Type) };
+    let r = match (code, arg) {
+        (Bytecode::CompareOp, op) if op <= Py_GE as u32 => unsafe {
+            PyObject_RichCompare(a, b, op as c_int)
+        },
+        (Bytecode::BinaryAdd, _) => unsafe { PyNumber_Add(a, b) },
+        (Bytecode::BinarySubtract, _) => unsafe { PyNumber_Subtract(a, b) },
+        (Bytecode::BinaryOp, NB_ADD | NB_SUBTRACT | NB_TRUE_DIVIDE | NB_FLOOR_DIVIDE | NB_AND)
+        | (Bytecode::BinaryOp, NB_OR | NB_XOR | NB_RSHIFT) => unsafe {
+            Py_INCREF(a);
+            Py_INCREF(b);
+            binary_op(a, b, arg)
+        },
+        (Bytecode::BinaryOp, NB_MULTIPLY | NB_REMAINDER) if !is_str(a) && !is_str(b) => unsafe {
+            Py_INCREF(a);
+            Py_INCREF(b);
+            binary_op(a, b, arg)
+        },
+        _ => return None,
+    };
+    if r.is_null() {
+        unsafe { PyErr_Clear() };
+        return None;
+    }
+    Some(r)
+}
+
+/// Returns the index of the instruction a jump goes to. Jumps in CPython 3.10 count
+/// instructions, not bytes. `PopJumpIfNone` and `PopJumpIfNotNone` do not exist in 3.10. They are
+/// made by `fuse_none_jump` and keep the absolute target of the jump they replace.
+fn jump_target(i: usize, code: &Bytecode, arg: u32) -> Option<usize> {
+    match code {
+        Bytecode::PopJumpIfFalse
+        | Bytecode::PopJumpIfTrue
+        | Bytecode::PopJumpIfNone
+        | Bytecode::PopJumpIfNotNone
+        | Bytecode::JumpIfFalseOrPop
+        | Bytecode::JumpIfTrueOrPop
+        | Bytecode::Jump
+        | Bytecode::JumpIfNotExcMatch => Some(arg as usize),
+        Bytecode::JumpForward
+        | Bytecode::SetupFinally
+        | Bytecode::SetupWith
+        | Bytecode::ForIter => Some(i + 1 + arg as usize),
+        _ => None,
+    }
+}
+
+fn decode_code(code_vec: &[u8]) -> Option<Vec<(Bytecode, u32)>> {
+    let mut decoded = Vec::new();
+    let mut extended_arg: u32 = 0;
+    for i in (0..code_vec.len()).step_by(2) {
+        let arg = extended_arg << 8 | code_vec[i + 1] as u32;
+        let (code, arg) = match decode(code_vec[i], arg) {
+            Some(c) => c,
+            None => {
+                info!("Unknown opcode:{:?}", code_vec[i]);
+                return None;
+            }
+        };
+        extended_arg = if code == Bytecode::ExtendedArg {
+            arg
+        } else {
+            0
+        };
+        decoded.push((code, arg));
+    }
+    Some(decoded)
+}
+
+pub fn compile_and_exec_jit_code(
+    state: *mut PyThreadState,
+    frame: *mut PyFrameObject,
+    c: i32,
+) -> Option<*mut PyObject> {
+    info!("compile_and_exec_jit_code");
+    // dump_frame_info(state, frame, c);
+
+    let f_code = unsafe { frame.read().f_code.read().co_code };
+    let is_bytes = unsafe { PyBytes_Check(f_code) };
+    let n_bytes = unsafe { PyBytes_Size(f_code) };
+    info!("is_bytes:{:?} n_bytes:{:?}", is_bytes, n_bytes);
+
+    let code_buf = unsafe { PyBytes_AsString(f_code) };
+    let mut code_vec: Vec<u8> = Vec::new();
+    for i in 0..n_bytes {
+        unsafe { code_vec.push(*code_buf.offset(i as isize) as u8) };
+    }
+    let code = decode_code(&code_vec)?;
+
+    // Show code
+    for (i, (code, arg)) in code.iter().enumerate() {
+        debug!("code_vec[{}]:{:?}, 0x{:02x?}", i * 2, code, arg);
+    }
+
+    // Most instructions take far less than the maximum, and the buffer grows when needed.
+    let buffer = CodeBuffer::new(code.len() * MAX_BYTES_PER_CODE / 4 + PAGE_SIZE);
+    let offset = write_prologue(buffer.ptr, 0);
+
+    // Compile
+    let mut compiler = Compiler::new(buffer, offset, frame, code, c != 0);
+    compiler.compile()?;
+    if std::env::var("RUST_LOG") == Result::Ok(String::from("debug")) {
+        log_disasm(
+            compiler.buf,
+            compiler.offset,
+            &compiler.code,
+            &compiler.labels,
+        );
+    }
+
+    // The compiler keeps the code and the guards it refers to until the code returns.
+    let code: JitFunction = unsafe { std::mem::transmute(compiler.buf) };
+
+    info!("Jump to code:{:x?}", code);
+    let retval = unsafe { code(state as *mut ThreadState, frame) };
+    if retval.is_null() {
+        info!("Return from code:{:x?} with an exception", code);
+        return Some(retval);
+    }
+    info!("Return from code:{:x?} retval:{:x?}", code, retval);
+    info!("type(retval):{:?}", get_type(retval));
+    return Some(retval);
+}
+
+fn log_disasm(code: *const u8, code_size: usize, py_code: &[(Bytecode, u32)], labels: &Labels) {
+    let mut code_vec: Vec<u8> = Vec::new();
+    for i in 0..code_size {
+        code_vec.push(unsafe { *code.offset(i as isize) });
+    }
+    let bytes = code_vec.as_slice();
+    let bitness = 64;
+    let rip = code as u64;
+    const HEXBYTES_COLUMN_BYTE_LENGTH: usize = 10;
+    let mut decoder = Decoder::with_ip(bitness, code_vec.as_slice(), rip, DecoderOptions::NONE);
+
+    // Formatters: Masm*, Nasm*, Gas* (AT&T) and Intel* (XED).
+    // For fastest code, see `SpecializedFormatter` which is ~3.3x faster. Use it if formatting
+    // speed is more important than being able to re-assemble formatted instructions.
+    let mut formatter = IntelFormatter::new();
+
+    // Change some options, there are many more
+    formatter.options_mut().set_digit_separator("`");
+    formatter.options_mut().set_first_operand_char_index(10);
+
+    // String implements FormatterOutput
+    let mut output = String::new();
 
     // Initialize this outside the loop because decode_out() writes to every field
     let mut instruction = Instruction::default();
@@ -559,16 +6240,10 @@ fn log_disasm(code: *const u8, code_size: usize, py_code_vec: Vec<u8>, bytes_per
     //      let instructions: Vec<_> = decoder.into_iter().collect();
     // but can_decode()/decode_out() is a little faster:
     while decoder.can_decode() {
-        // 8 is a magic number of endbr64 + push rbp + mov rsp to rbp
-        let prolouge_size = 8;
-        if decoder.position() % bytes_per_code as usize == prolouge_size {
-            let code: Bytecode = num::FromPrimitive::from_u8(
-                py_code_vec[(decoder.position() - prolouge_size) / bytes_per_code as usize * 2],
-            )
-            .unwrap();
-            let arg =
-                py_code_vec[(decoder.position() - prolouge_size) / bytes_per_code as usize * 2 + 1];
-            println!("; {:?}, 0x{:02x?}", code, arg);
+        for (i, (code, arg)) in py_code.iter().enumerate() {
+            if labels.offsets[i] == Some(decoder.position()) {
+                println!("; {:?}, 0x{:02x?}", code, arg);
+            }
         }
         // There's also a decode() method that returns an instruction but that also
         // means it copies an instruction (40 bytes):
@@ -1,19 +1,18 @@
+use super::bytecode::Bytecode;
 use log::info;
 use pyo3::ffi::{
     PyBytes_AsString, PyBytes_Check, PyBytes_Size, PyDict_Check, PyDict_Keys, PyFrameObject,
-    PyList_GetItem, PyList_Size, PyLongObject, PyLong_AsLong, PyLong_Check, PyObject,
-    PyThreadState, PyTuple_Check, PyTuple_GetItem, PyTuple_Size, PyUnicode_AsUTF8, PyUnicode_Check,
+    PyList_GetItem, PyList_Size, PyLong_AsLong, PyLong_Check, PyObject, PyThreadState,
+    PyTuple_Check, PyTuple_GetItem, PyTuple_Size, PyUnicode_AsUTF8, PyUnicode_Check,
 };
 use std::ffi::CStr;
-#[path = "bytecode.rs"]
-mod bytecode;
-use bytecode::Bytecode;
 
 fn c_bytes_to_string(b: *const i8) -> String {
     let c_str: &CStr = unsafe { CStr::from_ptr(b) };
     return c_str.to_str().unwrap().to_owned();
 }
 
+#[allow(dead_code)]
 fn show_code_vec(code_vec: &Vec<u8>) {
     for (i, c) in code_vec.iter().enumerate() {
         if i % 2 == 0 {
@@ -34,6 +33,7 @@ pub fn get_type(py_object: *mut PyObject) -> String {
     return unsafe { c_bytes_to_string(py_object.read().ob_type.read().tp_name) };
 }
 
+#[allow(dead_code)]
 fn str_to_string(s: *mut PyObject) -> String {
     assert_eq!(unsafe { PyUnicode_Check(s) }, 1);
     let u = unsafe { PyUnicode_AsUTF8(s) };
@@ -41,6 +41,7 @@ fn str_to_string(s: *mut PyObject) -> String {
     return s;
 }
 
+#[allow(dead_code)]
 fn get_co_varnames(co_varnames: *mut PyObject) -> Vec<String> {
     assert_eq!(unsafe { PyTuple_Check(co_varnames) }, 1);
     let n_co_varnames = unsafe { PyTuple_Size(co_varnames) };
@@ -54,6 +55,7 @@ fn get_co_varnames(co_varnames: *mut PyObject) -> Vec<String> {
     return ret;
 }
 
+#[allow(dead_code)]
 fn get_dict_keys(d: *mut PyObject) -> Vec<String> {
     assert_eq!(unsafe { PyDict_Check(d) }, 1);
     let ks = unsafe { PyDict_Keys(d) };
@@ -67,6 +69,7 @@ fn get_dict_keys(d: *mut PyObject) -> Vec<String> {
     return ret;
 }
 
+#[allow(dead_code)]
 fn get_jit_key(frame: *mut PyFrameObject) -> String {
     let mut fn_name = unsafe { str_to_string(frame.read().f_code.read().co_name) };
     let co_argcounts = unsafe { frame.read().f_code.read().co_argcount };
@@ -78,6 +81,7 @@ fn get_jit_key(frame: *mut PyFrameObject) -> String {
     return fn_name;
 }
 
+#[allow(dead_code)]
 pub fn dump_frame_info(_state: *mut PyThreadState, frame: *mut PyFrameObject, _c: i32) {
     info!("dump_frame_info");
     unsafe {
@@ -0,0 +1,119 @@
+import gc
+import rupyjit
+import weakref
+
+class Point:
+    def __init__(self, x, y):
+        self.x = x
+        self.y = y
+
+    def norm1(self):
+        return self.x + self.y
+
+class Slotted:
+    __slots__ = ("x", "y")
+
+class Tagged:
+    tag = 7
+
+class Prop:
+    @property
+    def x(self):
+        return 11
+
+def get_x(p):
+    return p.x
+
+def set_x(p, v):
+    p.x = v
+    return p.x
+
+def del_x(p):
+    del p.x
+    return 0
+
+def call_norm1(p):
+    return p.norm1
+
+def get_tag(p):
+    return p.tag
+
+rupyjit.enable()
+
+p = Point(1, 2)
+assert(get_x(p) == 1)
+assert(set_x(p, 5) == 5)
+assert(p.x == 5)
+
+s = Slotted()
+assert(set_x(s, 3) == 3)
+assert(get_x(s) == 3)
+del_x(s)
+try:
+    get_x(s)
+    assert(False)
+except AttributeError:
+    pass
+try:
+    del_x(s)
+    assert(False)
+except AttributeError:
+    pass
+
+# Class attributes, and an instance attribute which shadows one.
+t = Tagged()
+assert(get_tag(t) == 7)
+t.tag = 8
+assert(get_tag(t) == 8)
+Tagged.tag = 9
+assert(get_tag(Tagged()) == 9)
+
+assert(get_x(Prop()) == 11)
+try:
+    set_x(Prop(), 1)
+    assert(False)
+except AttributeError:
+    pass
+
+del_x(p)
+try:
+    get_x(p)
+    assert(False)
+except AttributeError:
+    pass
+
+# Bound methods from LoadAttr
+m = call_norm1(Point(3, 4))
+assert(m() == 7)
+
+# The caches of each access persist across calls and hold several types.
+s.x = 2
+objects = [Point(1, 0), s, Prop(), Point(4, 0)]
+for _ in range(3):
+    assert([get_x(o) for o in objects] == [1, 2, 11, 4])
+class Late:
+    pass
+Late.x = 13
+assert(get_x(Late()) == 13)
+Late.x = 14
+assert(get_x(Late()) == 14)
+# A data descriptor added to the class wins over the instance dict.
+Point.x = property(lambda self: 15)
+assert(get_x(objects[0]) == 15)
+
+# The caches live as long as the code object and keep what they found alive until then.
+class Attr:
+    pass
+
+def cached_attr():
+    class Holder:
+        attr = Attr()
+    ns = {}
+    exec("def get_attr(o):\n    return o.attr", ns)
+    for _ in range(2):
+        assert(ns["get_attr"](Holder()) is Holder.attr)
+    return weakref.ref(Holder.attr)
+
+r = cached_attr()
+gc.collect()
+assert(r() is None)
@@ -0,0 +1,104 @@
+import rupyjit
+
+def if_false(x):
+    if x:
+        return 42
+    return 24
+
+def if_true(x):
+    if not x:
+        return 24
+    return 42
+
+class Falsy:
+    def __bool__(self):
+        return False
+
+class Broken:
+    def __bool__(self):
+        raise ValueError("broken")
+
+rupyjit.enable()
+
+assert(if_false(True) == 42)
+assert(if_false(False) == 24)
+assert(if_false(None) == 24)
+assert(if_false(0) == 24)
+assert(if_false(7) == 42)
+assert(if_false("") == 24)
+assert(if_false([1]) == 42)
+assert(if_false(Falsy()) == 24)
+
+assert(if_true(True) == 42)
+assert(if_true(0.0) == 24)
+
+try:
+    if_false(Broken())
+    assert(False)
+except ValueError:
+    pass
+
+def is_none(x):
+    if x is None:
+        return 1
+    return 2
+
+def is_not_none(x):
+    if x is not None:
+        return 1
+    return 2
+
+def not_is_none(x):
+    if not x is None:
+        return 1
+    return 2
+
+class Node:
+    def __init__(self, next):
+        self.next = next
+
+def length(node):
+    n = 0
+    while node is not None:
+        n += 1
+        node = node.next
+    return n
+
+assert(is_none(None) == 1)
+assert(is_none(0) == 2)
+assert(is_none(Falsy()) == 2)
+assert(is_not_none(None) == 2)
+assert(is_not_none("") == 1)
+assert(not_is_none(None) == 2)
+assert(not_is_none([]) == 1)
+assert(length(None) == 0)
+assert(length(Node(Node(Node(None)))) == 3)
+
+def and_value(x, y):
+    return x and y
+
+def or_value(x, y):
+    return x or y
+
+def first_of(x, y, z):
+    return x or y or z
+
+assert(and_value(1, 2) == 2)
+assert(and_value(0, 2) == 0)
+assert(and_value(None, 2) is None)
+assert(and_value(True, "") == "")
+assert(and_value(False, 2) is False)
+assert(and_value([], 2) == [])
+assert(or_value(1, 2) == 1)
+assert(or_value(0, 2) == 2)
+assert(or_value(None, False) is False)
+assert(or_value(True, 2) is True)
+assert(or_value(Falsy(), "y") == "y")
+assert(first_of(None, 0, 3) == 3)
+assert(first_of("", [1], 3) == [1])
+
+try:
+    or_value(Broken(), 1)
+    assert(False)
+except ValueError:
+    pass
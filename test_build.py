@@ -0,0 +1,56 @@
+import rupyjit
+
+def build_tuple(a, b):
+    return (a, b, 3)
+
+def build_list(a, b):
+    return [a, b, a]
+
+def build_set(a, b):
+    return {a, b, 1}
+
+def build_map(a, b):
+    return {a: b, b: a}
+
+def build_const_key_map(a, b):
+    return {"x": a, "y": b}
+
+def unpack_list(a, b):
+    return [*a, b, *b]
+
+def unpack_set(a, b):
+    return {*a, *b}
+
+def unpack_map(a, b):
+    return {**a, "k": 1, **b}
+
+def unpack_tuple(a, b):
+    return (*a, *b)
+
+rupyjit.enable()
+
+assert(build_tuple(1, 2) == (1, 2, 3))
+assert(build_list("p", None) == ["p", None, "p"])
+assert(build_set(2, 2) == {1, 2})
+assert(build_map(1, 2) == {1: 2, 2: 1})
+assert(build_const_key_map(1, [2]) == {"x": 1, "y": [2]})
+assert(unpack_list((1, 2), [3]) == [1, 2, [3], 3])
+assert(unpack_set([1, 2], (2, 3)) == {1, 2, 3})
+assert(unpack_map({"a": 1}, {"k": 2}) == {"a": 1, "k": 2})
+assert(unpack_tuple([1], range(2, 4)) == (1, 2, 3))
+
+try:
+    build_set([], 1)
+    assert(False)
+except TypeError:
+    pass
+try:
+    unpack_list(1, [])
+    assert(False)
+except TypeError as e:
+    assert("must be an iterable" in str(e))
+try:
+    unpack_map({}, 1)
+    assert(False)
+except TypeError as e:
+    assert("not a mapping" in str(e))
@@ -0,0 +1,60 @@
+import rupyjit
+
+class Counter:
+    def __init__(self, start):
+        self.n = start
+
+    def add(self, a, b):
+        return self.n + a + b
+
+    def get(self):
+        return self.n
+
+class Callable:
+    def __init__(self):
+        self.f = lambda a: a * 2
+
+def double(a):
+    return a * 2
+
+def call_function(x):
+    return double(x) + 1
+
+def call_builtin(x):
+    return abs(x)
+
+def call_method(c, x):
+    return c.add(x, 1)
+
+def call_no_args(c):
+    return c.get()
+
+def call_builtin_method(s):
+    return s.upper()
+
+def call_attribute(c, x):
+    return c.f(x)
+
+def call_raises(c):
+    return c.missing()
+
+rupyjit.enable()
+
+assert(call_function(3) == 7)
+assert(call_builtin(-4) == 4)
+for _ in range(3):
+    assert(call_method(Counter(10), 5) == 16)
+    assert(call_no_args(Counter(2)) == 2)
+    assert(call_builtin_method("ab") == "AB")
+    # An instance attribute is not a method, so it is called without self.
+    assert(call_attribute(Callable(), 4) == 8)
+try:
+    call_raises(Counter(0))
+    assert(False)
+except AttributeError:
+    pass
+try:
+    call_function("a")
+    assert(False)
+except TypeError:
+    pass
@@ -0,0 +1,78 @@
+import rupyjit
+
+def counter():
+    n = 0
+    def inc(k):
+        nonlocal n
+        n += k
+        return n
+    return inc
+
+def adder(x):
+    def add(y):
+        return x + y
+    return add
+
+def deleter():
+    v = 1
+    def get():
+        return v
+    def drop():
+        nonlocal v
+        del v
+    return get, drop
+
+def unbound_cell():
+    def get():
+        return w
+    w
+    w = 1
+
+def shadowed():
+    x = 1
+    class C:
+        x = 2
+        y = x
+    return C
+
+class Base:
+    def __init__(self):
+        self.a = 1
+
+class Derived(Base):
+    def __init__(self):
+        super().__init__()
+        self.b = __class__
+
+inc = counter()
+add = adder(10)
+get, drop = deleter()
+
+rupyjit.enable()
+
+assert(inc(1) == 1)
+assert(inc(2) == 3)
+assert(inc(3) == 6)
+assert(add(5) == 15)
+assert(add(2.5) == 12.5)
+assert(get() == 1)
+drop()
+try:
+    get()
+    assert(False)
+except NameError as e:
+    assert("free variable 'v'" in str(e))
+try:
+    drop()
+    assert(False)
+except NameError:
+    pass
+try:
+    unbound_cell()
+    assert(False)
+except UnboundLocalError as e:
+    assert("'w'" in str(e))
+assert(shadowed().y == 2)
+d = Derived()
+assert(d.a == 1)
+assert(d.b is Derived)
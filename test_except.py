@@ -0,0 +1,157 @@
+import sys
+import traceback
+import rupyjit
+
+def div(x):
+    try:
+        return 1 // x
+    except ZeroDivisionError:
+        return -1
+
+def name(x):
+    try:
+        y = 1 // x
+    except (ZeroDivisionError, TypeError) as e:
+        y = type(e).__name__
+    return y
+
+def finally_(x, log):
+    try:
+        return 10 // x
+    finally:
+        log.append(x)
+
+def reraise(x):
+    try:
+        raise ValueError(x)
+    except ValueError:
+        raise
+
+def raise_class():
+    raise KeyError
+
+def raise_from(x):
+    raise KeyError(x) from ValueError("cause")
+
+def raise_from_none(x):
+    try:
+        return {}[x]
+    except KeyError:
+        raise AttributeError(x) from None
+
+def unwind(x, y):
+    # The call raises with values of the enclosing expression on the stack.
+    try:
+        return [x, y, (x, 1 // y)]
+    except ZeroDivisionError:
+        return [x]
+
+def nested(x):
+    try:
+        try:
+            return int(x)
+        except ValueError:
+            return 1 // 0
+    except ZeroDivisionError as e:
+        return str(e)
+
+def exc_info(x):
+    try:
+        raise ValueError(x)
+    except ValueError:
+        inner = sys.exc_info()[1]
+    return inner, sys.exc_info()[1]
+
+def not_matching(x):
+    try:
+        return 1 // x
+    except KeyError:
+        return 0
+
+def bad_class(x):
+    try:
+        return 1 // x
+    except 1:
+        return 0
+
+def bad_raise(x):
+    raise x
+
+def bare_raise():
+    raise
+
+K = 2
+
+def set_k(k):
+    global K
+    K = k
+
+def folded(x, k):
+    try:
+        set_k(k)
+        return K * 3 + x
+    except TypeError:
+        return -1
+
+rupyjit.enable()
+
+assert(div(2) == 0)
+assert(div(0) == -1)
+assert(name(0) == "ZeroDivisionError")
+assert(name("a") == "TypeError")
+assert(name(1) == 1)
+log = []
+assert(finally_(5, log) == 2)
+try:
+    finally_(0, log)
+    assert(False)
+except ZeroDivisionError:
+    pass
+assert(log == [5, 0])
+
+try:
+    reraise(3)
+    assert(False)
+except ValueError as e:
+    assert(e.args == (3,))
+    assert("reraise" in "".join(traceback.format_tb(e.__traceback__)))
+try:
+    raise_class()
+    assert(False)
+except KeyError as e:
+    assert(e.args == ())
+try:
+    raise_from(1)
+    assert(False)
+except KeyError as e:
+    assert(type(e.__cause__) is ValueError)
+try:
+    raise_from_none(1)
+    assert(False)
+except AttributeError as e:
+    assert(e.__cause__ is None and e.__suppress_context__)
+    assert(type(e.__context__) is KeyError)
+
+assert(unwind(1, 2) == [1, 2, (1, 0)])
+assert(unwind(1, 0) == [1])
+assert(nested("3") == 3)
+assert(nested("a") == "integer division or modulo by zero")
+inner, outer = exc_info(5)
+assert(type(inner) is ValueError and outer is None)
+assert(sys.exc_info() == (None, None, None))
+
+for f, args, exc in [(not_matching, (0,), ZeroDivisionError), (bad_class, (0,), TypeError),
+                     (bad_raise, (1,), TypeError), (bad_raise, (IndexError,), IndexError),
+                     (bare_raise, (), RuntimeError)]:
+    try:
+        f(*args)
+        assert(False)
+    except exc:
+        pass
+assert(bad_class(1) == 1)
+
+assert(folded(1, 2) == 7)
+assert(folded("a", 2) == -1)
+# Changing K resumes the interpreter inside the try block, which must know about the handler.
+assert(folded(1, 3) == 10)
+assert(folded("a", 4) == -1)
@@ -0,0 +1,69 @@
+import rupyjit
+
+def div(a, b):
+    return a / b
+
+def norm2(x, y):
+    return x * x + y * y
+
+def inside(x, y, r):
+    return x * x + y * y < r * r
+
+def lt(a, b):
+    return a < b
+
+def le(a, b):
+    return a <= b
+
+def eq(a, b):
+    return a == b
+
+def ne(a, b):
+    return a != b
+
+rupyjit.enable()
+
+r = div(7.0, 2.0)
+assert(r == 3.5)
+r = div(7, 2)
+assert(r == 3.5)
+r = div(1, 4.0)
+assert(r == 0.25)
+
+r = norm2(1.5, 2)
+assert(r == 6.25)
+
+r = inside(0.5, 0.5, 1)
+assert(r == True)
+r = inside(1, 1, 1.0)
+assert(r == False)
+
+nan = float("nan")
+assert(lt(1.0, 2.0) == True)
+assert(lt(2, 1.5) == False)
+assert(lt(nan, 1.0) == False)
+assert(le(2.0, 2) == True)
+assert(le(nan, nan) == False)
+assert(eq(2, 2.0) == True)
+assert(eq(nan, nan) == False)
+assert(ne(nan, nan) == True)
+assert(ne(1.0, 1) == False)
+
+# Division by zero raises like the interpreter
+try:
+    div(1.0, 0.0)
+    assert(False)
+except ZeroDivisionError:
+    pass
+
+# Ints which are not exactly representable as a double use the generic path
+r = div(2**29 * 2**29 * 2**29 + 1, 1)
+assert(r == float(2**87 + 1))
+
+def muldiv(a, b, c):
+    return a * b / c
+
+r = muldiv(2**29 + 1, 2**29 + 3, 1)
+assert(r == (2**29 + 1) * (2**29 + 3) / 1)
+r = muldiv(3, 5, 2)
+assert(r == 7.5)
@@ -0,0 +1,61 @@
+import rupyjit
+
+DEBUG = False
+SCALE = 3
+
+def debug_branch():
+    if DEBUG:
+        return 1
+    return 2
+
+def const_branch():
+    if 6 == 6:
+        return 42
+    return 24
+
+def scaled():
+    return SCALE * 2 + 1
+
+class Flip:
+    def __add__(self, other):
+        global DEBUG
+        DEBUG = True
+        return 0
+
+def bump():
+    global COUNT
+    COUNT = 1
+
+def rescale():
+    global SCALE
+    SCALE = 4
+
+def scaled_after(x, f):
+    f()
+    return x + 2 * SCALE
+
+def guarded(x):
+    if x + 0:
+        return 3
+    if DEBUG:
+        return 1
+    return 2
+
+rupyjit.enable()
+
+r = debug_branch()
+assert(r == 2)
+r = const_branch()
+assert(r == 42)
+r = scaled()
+assert(r == 7)
+
+# DEBUG is rebound while running compiled code, so the folded branch must not be taken.
+r = guarded(Flip())
+assert(r == 1)
+
+# The folded global is the second operand, so the interpreter resumes with x on the stack.
+r = scaled_after(1, bump)
+assert(r == 7)
+r = scaled_after(1, rescale)
+assert(r == 9)
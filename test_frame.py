@@ -0,0 +1,43 @@
+import sys
+import rupyjit
+
+def current():
+    return sys._getframe()
+
+def caller():
+    f = current()
+    return f, sys._getframe()
+
+def raises():
+    f = sys._getframe()
+    raise ValueError(f)
+
+def gen():
+    yield sys._getframe().f_back
+    yield sys._getframe().f_back
+
+def formats(x):
+    # Float formatting uses aligned SSE stores, with values at odd and even depths.
+    return ("%.2f" % x, (1, "%.2f" % x), (1, 2, "%.2f" % x), [x, (3, "%e" % x)])
+
+rupyjit.enable()
+
+outer = sys._getframe()
+for _ in range(3):
+    inner, f = caller()
+    assert(f.f_code is caller.__code__)
+    assert(inner.f_code is current.__code__)
+    assert(inner.f_back is f)
+    assert(f.f_back is outer)
+    assert(sys._getframe() is outer)
+    try:
+        raises()
+        assert(False)
+    except ValueError as e:
+        assert(e.args[0].f_back is outer)
+    assert(sys._getframe() is outer)
+    g = gen()
+    assert(next(g) is outer)
+    assert(list(g) == [outer])
+    assert(sys._getframe() is outer)
+    assert(formats(1.5) == ("1.50", (1, "1.50"), (1, 2, "1.50"), [1.5, (3, "1.500000e+00")]))
@@ -0,0 +1,61 @@
+import rupyjit
+
+class Thing:
+    def __str__(self):
+        return "str"
+    def __repr__(self):
+        return "repr"
+    def __format__(self, spec):
+        return "format:" + spec
+
+class Broken:
+    def __format__(self, spec):
+        raise ValueError("format")
+
+def simple(x):
+    return f"{x}"
+
+def mixed(name, n):
+    return f"name={name} n={n}!"
+
+def conversions(x):
+    return f"{x!s}|{x!r}|{x!a}"
+
+def spec(x):
+    return f"{x:>6}|{x:06.2f}"
+
+def nested_spec(x, width):
+    return f"{x:>{width}}"
+
+def conversion_spec(x):
+    return f"[{x!r:>8}]"
+
+def logging(level, msg, count):
+    return f"[{level}] {msg} ({count} items, {count / 4:.1f} avg)"
+
+rupyjit.enable()
+
+assert(simple("abc") == "abc")
+assert(simple(42) == "42")
+assert(simple(True) == "True")
+assert(simple(2 ** 80) == str(2 ** 80))
+assert(simple(1.5) == "1.5")
+assert(simple(Thing()) == "format:")
+assert(mixed("x", 3) == "name=x n=3!")
+assert(conversions("é") == "é|'é'|'\\xe9'")
+assert(conversions(Thing()) == "str|repr|repr")
+assert(spec(3.14159) == "3.14159|003.14")
+assert(nested_spec("a", 3) == "  a")
+assert(nested_spec(Thing(), 2) == "format:>2")
+assert(conversion_spec("a") == "[     'a']")
+assert(logging("INFO", "done", 10) == "[INFO] done (10 items, 2.5 avg)")
+try:
+    simple(Broken())
+    assert(False)
+except ValueError:
+    pass
+try:
+    spec("abc")
+    assert(False)
+except ValueError:
+    pass
@@ -0,0 +1,53 @@
+import rupyjit
+
+def make_adder(x):
+    def add(y):
+        return x + y
+    return add
+
+def make_defaults(a, b):
+    def f(x=a, *, y=b):
+        return x * 10 + y
+    return f
+
+def make_lambda(k):
+    return lambda v: v * k
+
+def annotated():
+    def f(x: int) -> str:
+        return str(x)
+    return f
+
+def decorate(g):
+    def wrapper(*args):
+        return g(*args) + 1
+    return wrapper
+
+def make_class(v):
+    class C:
+        def get(self):
+            return v
+    return C
+
+def build_class():
+    return __build_class__
+
+rupyjit.enable()
+
+assert(make_adder(3)(4) == 7)
+add = make_adder(1)
+assert(add.__qualname__ == "make_adder.<locals>.add")
+assert(add.__closure__[0].cell_contents == 1)
+f = make_defaults(1, 2)
+assert(f() == 12)
+assert(f(3, y=4) == 34)
+assert(f.__defaults__ == (1,))
+assert(f.__kwdefaults__ == {"y": 2})
+assert(make_lambda(3)(5) == 15)
+assert(annotated().__annotations__ == {"x": int, "return": str})
+assert(annotated()(5) == "5")
+assert(decorate(lambda a, b: a + b)(1, 2) == 4)
+C = make_class(5)
+assert(C.__name__ == "C")
+assert(C().get() == 5)
+assert(build_class() is __build_class__)
@@ -0,0 +1,149 @@
+import types
+import rupyjit
+
+def count(n):
+    for i in range(n):
+        yield i
+
+def echo():
+    received = []
+    x = yield 1
+    received.append(x)
+    x = yield 2
+    received.append(x)
+    return received
+
+def delegate(n):
+    result = yield from echo()
+    yield result
+    yield from count(n)
+
+def guarded(log):
+    try:
+        yield 1
+        yield 2
+    except KeyError as e:
+        log.append(e.args)
+        yield 3
+    finally:
+        log.append("finally")
+
+def raising():
+    yield 1
+    raise ValueError("gen")
+
+def stop():
+    yield 1
+    raise StopIteration
+
+def total(xs):
+    s = 0
+    for x in xs:
+        s = s + x
+    return s
+
+class Awaitable:
+    def __await__(self):
+        x = yield "suspended"
+        return x * 2
+
+async def coro(a):
+    x = await a
+    return x + 1
+
+async def coro_chain():
+    return await coro(Awaitable())
+
+@types.coroutine
+def legacy():
+    yield "legacy"
+    return 5
+
+async def await_legacy():
+    return await legacy()
+
+async def await_bad():
+    return await 1
+
+rupyjit.enable()
+
+assert(list(count(3)) == [0, 1, 2])
+assert(total([1, 2, 3]) == 6)
+assert(total(count(5)) == 10)
+
+g = echo()
+assert(next(g) == 1)
+assert(g.send("a") == 2)
+try:
+    g.send("b")
+    assert(False)
+except StopIteration as e:
+    assert(e.value == ["a", "b"])
+
+g = delegate(2)
+assert(next(g) == 1)
+assert(g.send("x") == 2)
+assert(g.send("y") == ["x", "y"])
+assert(list(g) == [0, 1])
+
+log = []
+g = guarded(log)
+assert(next(g) == 1)
+assert(g.throw(KeyError("k")) == 3)
+g.close()
+assert(log == [("k",), "finally"])
+log = []
+g = guarded(log)
+assert(list(g) == [1, 2])
+assert(log == ["finally"])
+log = []
+g = guarded(log)
+next(g)
+try:
+    g.throw(IndexError)
+    assert(False)
+except IndexError:
+    pass
+assert(log == ["finally"])
+assert(g.gi_frame is None)
+
+g = raising()
+assert(next(g) == 1)
+assert(not g.gi_running)
+try:
+    next(g)
+    assert(False)
+except ValueError:
+    pass
+try:
+    list(stop())
+    assert(False)
+except RuntimeError as e:
+    assert(type(e.__cause__) is StopIteration)
+
+c = coro(Awaitable())
+assert(c.send(None) == "suspended")
+try:
+    c.send(20)
+    assert(False)
+except StopIteration as e:
+    assert(e.value == 41)
+c = coro_chain()
+assert(c.send(None) == "suspended")
+try:
+    c.send(1)
+    assert(False)
+except StopIteration as e:
+    assert(e.value == 3)
+c = await_legacy()
+assert(c.send(None) == "legacy")
+try:
+    c.send(None)
+    assert(False)
+except StopIteration as e:
+    assert(e.value == 5)
+try:
+    await_bad().send(None)
+    assert(False)
+except TypeError:
+    pass
@@ -0,0 +1,44 @@
+import rupyjit
+
+VALUE = 1
+
+def load():
+    return VALUE
+
+def builtin():
+    return len
+
+def missing():
+    return UNDEFINED
+
+class Rebind:
+    def __add__(self, other):
+        global VALUE, max
+        VALUE = 10
+        max = 5
+        return 0
+
+def reload(x):
+    return (x + 0) + VALUE
+
+def shadow(x):
+    return (x + 0) + max
+
+rupyjit.enable()
+
+r = load()
+assert(r == 1)
+r = builtin()
+assert(r is len)
+try:
+    missing()
+    assert(False)
+except NameError as e:
+    assert("UNDEFINED" in str(e))
+
+# The globals change while running compiled code, so the values found while compiling are stale.
+r = reload(Rebind())
+assert(r == 10)
+del max
+r = shadow(Rebind())
+assert(r == 5)
@@ -0,0 +1,74 @@
+import builtins
+import rupyjit
+
+def import_json():
+    import json
+    return json.dumps([1])
+
+def import_as():
+    import os.path as p
+    return p.join("a", "b")
+
+def import_dotted():
+    import os.path
+    return os.path.basename("/x/y")
+
+def from_import():
+    from math import sqrt, pi
+    return sqrt(4.0) + pi
+
+def from_submodule():
+    from xml import dom
+    return dom.__name__
+
+def missing_module():
+    import no_such_module_here
+
+def missing_name():
+    from math import no_such_name
+
+def relative():
+    from . import something
+
+def with_override():
+    import math
+    return math
+
+rupyjit.enable()
+
+assert(import_json() == "[1]")
+assert(import_as() == "a/b")
+assert(import_dotted() == "y")
+assert(from_import() == 2.0 + 3.141592653589793)
+assert(from_submodule() == "xml.dom")
+try:
+    missing_module()
+    assert(False)
+except ModuleNotFoundError as e:
+    assert(e.name == "no_such_module_here")
+try:
+    missing_name()
+    assert(False)
+except ImportError as e:
+    assert("cannot import name 'no_such_name' from 'math'" in str(e))
+    assert(e.name == "math")
+try:
+    relative()
+    assert(False)
+except ImportError as e:
+    assert("relative import" in str(e))
+
+calls = []
+original = builtins.__import__
+def fake_import(name, globals=None, locals=None, fromlist=(), level=0):
+    calls.append((name, fromlist, level))
+    return original(name, globals, locals, fromlist, level)
+builtins.__import__ = fake_import
+try:
+    import math
+    assert(with_override() is math)
+    assert(from_import() == 2.0 + 3.141592653589793)
+finally:
+    builtins.__import__ = original
+assert(calls[-2] == ("math", None, 0))
+assert(calls[-1] == ("math", ("sqrt", "pi"), 0))
@@ -0,0 +1,68 @@
+import sys
+import types
+import rupyjit
+
+class Pos:
+    def __pos__(self):
+        return "pos"
+
+def positive(x):
+    return +x
+
+def concat(a, b):
+    return (*a, *b)
+
+def concat_more(a, b):
+    return (0, *a, 1, *b, 2)
+
+bad = types.ModuleType("bad")
+bad.__all__ = ["ok", 1]
+bad.ok = 1
+hidden = types.ModuleType("hidden")
+hidden.public = 1
+hidden._private = 2
+
+rupyjit.enable()
+
+assert(positive(3) == 3)
+assert(positive(-2.5) == -2.5)
+assert(positive(True) == 1)
+assert(positive(Pos()) == "pos")
+try:
+    positive("a")
+    assert(False)
+except TypeError:
+    pass
+assert(concat([1, 2], (3,)) == (1, 2, 3))
+assert(concat("ab", range(2)) == ("a", "b", 0, 1))
+assert(concat_more([], {5: 6}) == (0, 1, 5, 2))
+try:
+    concat(1, [])
+    assert(False)
+except TypeError:
+    pass
+
+ns = {}
+exec("from math import *\nr = floor(pi)", ns)
+assert(ns["r"] == 3)
+assert("sqrt" in ns)
+sys.modules["hidden"] = hidden
+ns = {}
+exec("from hidden import *", ns)
+assert("public" in ns and "_private" not in ns)
+sys.modules["bad"] = bad
+try:
+    exec("from bad import *", {})
+    assert(False)
+except TypeError as e:
+    assert(str(e) == "Item in bad.__all__ must be str, not int")
+
+shown = []
+old = sys.displayhook
+sys.displayhook = shown.append
+try:
+    exec(compile("1 + 2", "<stdin>", "single"), {})
+    exec(compile("None", "<stdin>", "single"), {})
+finally:
+    sys.displayhook = old
+assert(shown == [3, None])
@@ -0,0 +1,118 @@
+import collections
+import rupyjit
+
+class Point:
+    __match_args__ = ("x", "y")
+    def __init__(self, x, y):
+        self.x = x
+        self.y = y
+
+class NoArgs:
+    pass
+
+class BadArgs:
+    __match_args__ = ("missing", 1)
+
+class BadArgsLater:
+    __match_args__ = ("x", 1)
+    x = 0
+
+def parse(command):
+    match command:
+        case []:
+            return "empty"
+        case ["go", direction]:
+            return "go " + direction
+        case ["drop", *objects]:
+            return "drop " + ",".join(objects)
+        case {"action": action, "target": target, **rest}:
+            return (action, target, rest)
+        case Point(x=0, y=0):
+            return "origin"
+        case Point(x, y) if x == y:
+            return ("diagonal", x)
+        case Point(x, y):
+            return ("point", x, y)
+        case int(n) | float(n):
+            return ("number", n)
+        case str() as s:
+            return ("string", s)
+        case {"kind": "a"} | {"kind": "b"}:
+            return "kind"
+        case _:
+            return None
+
+def length(seq):
+    match seq:
+        case [a, b, c, *_]:
+            return 3
+        case [a, b]:
+            return 2
+        case _:
+            return 0
+
+def too_many(p):
+    match p:
+        case Point(a, b, c):
+            return 1
+
+def not_a_type(p):
+    match p:
+        case len():
+            return 1
+
+def duplicate_attr(p):
+    match p:
+        case Point(x, x=1):
+            return 1
+
+def no_match_args(p):
+    match p:
+        case NoArgs(x):
+            return 1
+
+def bad_args(p):
+    match p:
+        case BadArgs(a, b):
+            return 1
+        case BadArgsLater(a, b):
+            return 2
+        case _:
+            return 3
+
+rupyjit.enable()
+
+assert(parse([]) == "empty")
+assert(parse(["go", "north"]) == "go north")
+assert(parse(("drop", "a", "b")) == "drop a,b")
+assert(parse({"action": "hit", "target": "t", "extra": 1}) == ("hit", "t", {"extra": 1}))
+assert(parse(collections.OrderedDict(action=1, target=2)) == (1, 2, {}))
+assert(parse(collections.defaultdict(int, kind="b")) == "kind")
+assert(parse({"kind": "c"}) is None)
+assert(parse(Point(0, 0)) == "origin")
+assert(parse(Point(2, 2)) == ("diagonal", 2))
+assert(parse(Point(1, 2)) == ("point", 1, 2))
+assert(parse(5) == ("number", 5))
+assert(parse(2.5) == ("number", 2.5))
+assert(parse("go") == ("string", "go"))
+assert(parse(b"go") is None)
+assert(length([1, 2, 3, 4]) == 3)
+assert(length((1, 2)) == 2)
+assert(length("ab") == 0)
+assert(length(range(3)) == 3)
+assert(length({1: 2, 3: 4}) == 0)
+for f, exc in [(too_many, TypeError), (not_a_type, TypeError), (duplicate_attr, TypeError),
+               (no_match_args, TypeError)]:
+    try:
+        f(Point(1, 2)) if f is not no_match_args else f(NoArgs())
+        assert(False)
+    except exc:
+        pass
+
+# A missing attribute ends the match before the name which is not a str is checked
+assert(bad_args(BadArgs()) == 3)
+try:
+    bad_args(BadArgsLater())
+    assert(False)
+except TypeError:
+    pass
@@ -0,0 +1,95 @@
+import rupyjit
+
+counter = 0
+
+def bump():
+    global counter
+    counter += 1
+    return counter
+
+def drop_global():
+    global temp
+    del temp
+
+def make_global():
+    global temp
+    temp = "t"
+
+class Recorder(dict):
+    def __init__(self):
+        super().__init__()
+        self.log = []
+    def __getitem__(self, key):
+        self.log.append(key)
+        return super().__getitem__(key)
+
+script = """
+import math
+x = 2
+y = x * 3
+def f():
+    return y + 1
+z = f()
+del x
+total = 0
+for i in range(5):
+    total += i
+n: int = 4
+label = f"{total}-{n}"
+class C:
+    a = y
+    b = a + 1
+    def m(self):
+        return self.b
+w = C().m()
+"""
+
+rupyjit.enable()
+
+assert(bump() == 1)
+assert(bump() == 2)
+assert(counter == 2)
+make_global()
+assert(temp == "t")
+drop_global()
+try:
+    drop_global()
+    assert(False)
+except NameError as e:
+    assert(str(e) == "name 'temp' is not defined")
+    assert(e.name == "temp")
+
+ns = {}
+exec(script, ns)
+assert("x" not in ns)
+assert(ns["y"] == 6)
+assert(ns["z"] == 7)
+assert(ns["total"] == 10)
+assert(ns["__annotations__"] == {"n": int})
+assert(ns["label"] == "10-4")
+assert(ns["C"].__qualname__ == "C")
+assert(ns["C"].__module__ == "builtins")
+assert(ns["C"].b == 7)
+assert(ns["w"] == 7)
+
+locals_ = Recorder()
+g = {"gv": 5}
+exec("lv = 1\nr = lv + gv + len('ab')", g, locals_)
+assert(locals_["r"] == 8)
+assert(locals_.log[:3] == ["lv", "gv", "len"])
+
+try:
+    exec("undefined_name", {})
+    assert(False)
+except NameError as e:
+    assert(e.name == "undefined_name")
+try:
+    exec("del undefined_name", {})
+    assert(False)
+except NameError as e:
+    assert(str(e) == "name 'undefined_name' is not defined")
+
+class Shadow:
+    len = 3
+    value = len
+assert(Shadow.value == 3)
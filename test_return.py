@@ -0,0 +1,92 @@
+import sys
+import rupyjit
+
+def implicit(x):
+    x + 1
+
+def constant():
+    return (1, "two")
+
+def folded():
+    return 2 * 3 + 1
+
+def branch(x):
+    if x:
+        return "yes"
+    return "no"
+
+def in_loop(items):
+    for item in items:
+        for c in item:
+            if c == "!":
+                return c
+    return None
+
+class Manager:
+    def __init__(self):
+        self.exited = 0
+    def __enter__(self):
+        return self
+    def __exit__(self, *args):
+        self.exited += 1
+
+def in_with(m):
+    with m:
+        return "with"
+
+def in_finally(log):
+    try:
+        return "try"
+    finally:
+        log.append("finally")
+
+def in_except():
+    try:
+        raise ValueError
+    except ValueError:
+        return "except"
+
+def gen():
+    yield 1
+    return "done"
+
+def gen_const():
+    yield 1
+
+def leftover():
+    return None, t
+
+# LOAD_CONST 0; LOAD_GLOBAL t; LOAD_CONST 0; RETURN_VALUE, leaving two values below the result
+leftover.__code__ = leftover.__code__.replace(
+    co_code=bytes([100, 0, 116, 0, 100, 0, 83, 0]), co_stacksize=3)
+
+rupyjit.enable()
+
+t = constant()
+r0 = sys.getrefcount(t)
+for _ in range(100):
+    assert(implicit(1) is None)
+    assert(constant() is t)
+    assert(folded() == 7)
+    assert(branch(1) == "yes")
+    assert(branch(0) == "no")
+    assert(in_loop(["ab", "c!d"]) == "!")
+    assert(in_loop(["ab"]) is None)
+    m = Manager()
+    assert(in_with(m) == "with")
+    assert(m.exited == 1)
+    log = []
+    assert(in_finally(log) == "try")
+    assert(log == ["finally"])
+    assert(in_except() == "except")
+    assert(sys.exc_info() == (None, None, None))
+    g = gen()
+    assert(next(g) == 1)
+    try:
+        next(g)
+        assert(False)
+    except StopIteration as e:
+        assert(e.value == "done")
+    assert(list(gen_const()) == [1])
+    assert(leftover() is None)
+assert(sys.getrefcount(t) == r0)
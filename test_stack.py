@@ -0,0 +1,47 @@
+import rupyjit
+
+log = []
+
+def record(x):
+    log.append(x)
+
+def statements(x):
+    record(x)
+    record(x + 1)
+    return len(log)
+
+def swap(a, b):
+    a, b = b, a
+    return [a, b]
+
+def rotate(a, b, c):
+    a, b, c = c, a, b
+    return [a, b, c]
+
+def chained(a, b, c):
+    return a < b < c
+
+def augmented(l, i):
+    l[i] += 10
+    return l
+
+def augmented_attr(o):
+    o.n += 1
+    return o.n
+
+class Counter:
+    n = 0
+
+rupyjit.enable()
+
+assert(statements(1) == 2)
+assert(log == [1, 2])
+assert(swap(1, 2) == [2, 1])
+assert(rotate(1, 2, 3) == [3, 1, 2])
+assert(chained(1, 2, 3) == True)
+assert(chained(1, 3, 2) == False)
+assert(chained(2, 1, 3) == False)
+assert(augmented([1, 2], 1) == [1, 12])
+c = Counter()
+assert(augmented_attr(c) == 1)
+assert(augmented_attr(c) == 2)
@@ -0,0 +1,74 @@
+import rupyjit
+
+def get(c, k):
+    return c[k]
+
+def get_const(c):
+    return c[1]
+
+def get_slice(c, a, b):
+    return c[a:b]
+
+def get_step(c):
+    return c[::2]
+
+def set_item(c, k, v):
+    c[k] = v
+    return c
+
+def del_item(c, k):
+    del c[k]
+    return c
+
+def set_slice(c, a, b, v):
+    c[a:b] = v
+    return c
+
+class Missing(dict):
+    def __missing__(self, key):
+        return key * 2
+
+rupyjit.enable()
+
+l = [10, 20, 30]
+t = (1, 2, 3)
+for _ in range(2):
+    assert(get(l, 0) == 10)
+    assert(get(l, -1) == 30)
+    assert(get(t, 2) == 3)
+    assert(get(t, -3) == 1)
+    assert(get_const(l) == 20)
+    assert(get({"a": 1}, "a") == 1)
+    assert(get("abc", 1) == "b")
+    assert(get(l, True) == 20)
+    assert(get(Missing(), 4) == 8)
+    assert(get(l, 2 ** 40 - 2 ** 40 + 1) == 20)
+# The item outlives the container and the key.
+assert(get([[1, 2]], 0) == [1, 2])
+assert(get_slice(l, 1, None) == [20, 30])
+assert(get_slice("hello", -3, -1) == "ll")
+assert(get_step(t) == (1, 3))
+
+for c, k, e in [(l, 3, IndexError), (l, -4, IndexError), (t, 5, IndexError),
+                ({}, "x", KeyError), (l, "x", TypeError), (t, 2 ** 40, IndexError)]:
+    try:
+        get(c, k)
+        assert(False)
+    except e:
+        pass
+
+assert(set_item([1, 2], 0, 5) == [5, 2])
+assert(set_item({}, "k", 1) == {"k": 1})
+assert(del_item([1, 2, 3], -1) == [1, 2])
+assert(del_item({"k": 1}, "k") == {})
+assert(set_slice([1, 2, 3], 0, 2, [7]) == [7, 3])
+try:
+    set_item((1,), 0, 1)
+    assert(False)
+except TypeError:
+    pass
+try:
+    del_item({}, "k")
+    assert(False)
+except KeyError:
+    pass
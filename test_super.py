@@ -0,0 +1,128 @@
+import rupyjit
+
+class A:
+    kind = "a"
+    def __init__(self, x):
+        self.x = x
+    def describe(self):
+        return "A" + str(self.x)
+    @property
+    def size(self):
+        return self.x * 2
+    @classmethod
+    def make(cls, x):
+        obj = cls.__new__(cls)
+        obj.x = x
+        return obj
+    @staticmethod
+    def tag():
+        return "tag"
+
+class B(A):
+    kind = "b"
+    def __init__(self, x, y):
+        super().__init__(x)
+        self.y = y
+    def describe(self):
+        return "B" + super().describe()
+    @property
+    def size(self):
+        return super().size + 1
+    @classmethod
+    def make(cls, x):
+        return super().make(x + 1)
+    def parent_kind(self):
+        return super().kind
+    def tag(self):
+        return super().tag() + "!"
+    def missing(self):
+        return super().nothing
+
+class C(B):
+    def __init__(self):
+        super().__init__(1, 2)
+        self.z = 3
+    def describe(self):
+        return "C" + super().describe()
+    def captured(self):
+        get = lambda: self.z
+        return super().describe() + str(get())
+    def explicit(self):
+        return super(B, self).describe()
+    def chain(self):
+        return super().__class__
+
+class Left(A):
+    def describe(self):
+        return "L" + super().describe()
+
+class Right(A):
+    def describe(self):
+        return "R" + super().describe()
+
+class Diamond(Left, Right):
+    def describe(self):
+        return "D" + super().describe()
+
+def factory(base):
+    class Made(base):
+        def describe(self):
+            return "M" + super().describe()
+    return Made
+
+def fake_super(*args):
+    raise AssertionError("global super called with %d args" % len(args))
+
+class Rebound(A):
+    def describe(self):
+        try:
+            return super().describe()
+        except AssertionError as e:
+            return str(e)
+
+def no_class():
+    return super().describe()
+
+def early():
+    class Early:
+        try:
+            super().__init__()
+        except RuntimeError as e:
+            error = str(e)
+    return Early.error
+
+rupyjit.enable()
+
+for _ in range(3):
+    c = C()
+    assert((c.x, c.y, c.z) == (1, 2, 3))
+    assert(c.describe() == "CBA1")
+    assert(c.explicit() == "A1")
+    assert(c.captured() == "BA13")
+    assert(c.size == 3)
+    assert(c.parent_kind() == "a")
+    assert(c.tag() == "tag!")
+    assert(c.chain() is super)
+    assert(B.make(1).x == 2)
+    assert(type(C.make.__func__(B, 4)) is B)
+    assert(Diamond(5).describe() == "DLRA5")
+    assert(factory(B)(6, 0).describe() == "MBA6")
+    assert(factory(Diamond)(7).describe() == "MDLRA7")
+    assert(Rebound(1).describe() == "A1")
+    super = fake_super
+    assert(Rebound(1).describe() == "global super called with 0 args")
+    del super
+    try:
+        c.missing()
+        assert(False)
+    except AttributeError as e:
+        assert(str(e) == "'super' object has no attribute 'nothing'")
+
+A.describe = lambda self: "patched"
+assert(C().describe() == "CBpatched")
+try:
+    no_class()
+    assert(False)
+except RuntimeError as e:
+    assert(str(e) == "super(): no arguments")
+assert(early() == "super(): no arguments")
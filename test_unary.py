@@ -0,0 +1,83 @@
+import rupyjit
+
+def neg(x):
+    return -x
+
+def inv(x):
+    return ~x
+
+def neg_expr(x, y):
+    return -x * y + ~y
+
+def not_(x):
+    return not x
+
+def is_none(x):
+    return x is None
+
+def is_not_none(x):
+    return x is not None
+
+def branch_is(x):
+    if x is None:
+        return 1
+    return 2
+
+def branch_is_not(x, y):
+    if x is not y:
+        return 1
+    return 2
+
+def contains(x, c):
+    return x in c
+
+def not_contains(x, c):
+    return x not in c
+
+class Broken:
+    def __bool__(self):
+        raise ValueError("bool")
+
+class Weird:
+    def __contains__(self, x):
+        return x == 3
+
+rupyjit.enable()
+
+assert(neg(3) == -3)
+assert(neg(-2.5) == 2.5)
+assert(str(neg(0.0)) == "-0.0")
+assert(neg(-(2 ** 63)) == 2 ** 63)
+assert(neg(2 ** 70) == -(2 ** 70))
+assert(neg(True) == -1)
+assert(inv(5) == -6)
+assert(inv(2 ** 70) == -(2 ** 70) - 1)
+assert(neg_expr(2, 3) == -10)
+assert(neg_expr(2.0, 3) == -10.0)
+assert(not_(0) == True)
+assert(not_([1]) == False)
+assert(is_none(None) == True)
+assert(is_none(0) == False)
+assert(is_not_none(0) == True)
+assert(branch_is(None) == 1)
+assert(branch_is([]) == 2)
+o = object()
+assert(branch_is_not(o, o) == 2)
+assert(branch_is_not(o, object()) == 1)
+assert(contains(1, [1, 2]) == True)
+assert(contains("a", {"a": 1}) == True)
+assert(contains(3, {1, 2}) == False)
+assert(contains(1, frozenset([1])) == True)
+assert(contains("bc", "abcd") == True)
+assert(contains(3, Weird()) == True)
+assert(not_contains(3, (1, 2)) == True)
+assert(not_contains(1, {1}) == False)
+
+for f, args, exc in [(neg, ("a",), TypeError), (inv, (1.0,), TypeError),
+                     (not_, (Broken(),), ValueError), (contains, (1, 2), TypeError),
+                     (contains, ([], {}), TypeError)]:
+    try:
+        f(*args)
+        assert(False)
+    except exc:
+        pass
@@ -0,0 +1,54 @@
+import rupyjit
+
+def muladd(a, b, c, d):
+    return a * b + c * d
+
+def poly(x):
+    return x * x - 3 * x + 2
+
+def mul4(a, b, c, d):
+    return a * b * c * d
+
+def mul3_add(a, b, c, d):
+    return a * b * c + d
+
+def mul3_sub(a, b, c, d):
+    return a * b * c - d
+
+def neg_mul3(a, b, c):
+    return -(a * b * c)
+
+rupyjit.enable()
+
+r = muladd(3, 4, 5, 6)
+assert(r == 42)
+
+r = muladd(1.5, 2.0, 0.25, 4.0)
+assert(r == 4.0)
+
+r = poly(-7)
+assert(r == 72)
+
+# Products of compact ints beyond 32 bits stay unboxed
+r = muladd(2**29, 2**29, 2**29, 2**29)
+assert(r == 2**59)
+r = poly(2**29)
+assert(r == (2**29) * (2**29) - 3 * (2**29) + 2)
+
+# Overflow of 64 bits falls back to the generic path
+r = mul4(2**29, 2**29, 2**29, 2**29)
+assert(r == 2**116)
+r = mul4(-(2**29), 2**29, 2**29, 2**29)
+assert(r == -(2**116))
+r = mul3_add(454279, 31252369, 649657, 1)  # 2**63 - 1 before adding
+assert(r == 2**63)
+r = mul3_sub(-(2**29), 2**29, 2**5, 1)
+assert(r == -(2**63) - 1)
+r = neg_mul3(-(2**29), 2**29, 2**5)
+assert(r == 2**63)
+r = mul3_add(2**29, 2**29, 2**4, 5)
+assert(r == 2**62 + 5)
+
+# Types other than the ones seen while compiling use the generic path
+r = muladd(3, 4.0, 5, 6)
+assert(r == 42.0)
@@ -0,0 +1,68 @@
+import rupyjit
+
+def pair():
+    return (1, 2)
+
+def swap_sum(t):
+    a, b = t
+    return b - a
+
+def starred(t):
+    a, *b, c = t
+    return [a, b, c]
+
+def starred_head(t):
+    *a, b = t
+    return (a, b)
+
+def rebind(x):
+    y = x
+    y = y + 1
+    return y
+
+def unbound(flag):
+    if flag:
+        z = 1
+    return z
+
+def delete(x):
+    y = x
+    del y
+    return y
+
+class Box:
+    pass
+
+def unpack_attrs(b, t):
+    b.x, b.y = t
+    return b.x * 10 + b.y
+
+rupyjit.enable()
+
+assert(swap_sum((1, 5)) == 4)
+assert(swap_sum([1, 5]) == 4)
+assert(swap_sum({1: 0, 3: 0}) == 2)
+assert(swap_sum(range(3, 5)) == 1)
+assert(swap_sum(x for x in (2, 9)) == 7)
+assert(starred((1, 2, 3, 4)) == [1, [2, 3], 4])
+assert(starred([1, 2]) == [1, [], 2])
+assert(starred_head(range(3)) == ([0, 1], 2))
+assert(rebind(3) == 4)
+assert(unbound(True) == 1)
+assert(unpack_attrs(Box(), (3, 4)) == 34)
+
+def raises(f, arg, exc, message):
+    try:
+        f(arg)
+        assert(False)
+    except exc as e:
+        assert(str(e) == message), str(e)
+
+raises(swap_sum, (1,), ValueError, "not enough values to unpack (expected 2, got 1)")
+raises(swap_sum, [1, 2, 3], ValueError, "too many values to unpack (expected 2)")
+raises(swap_sum, iter(range(3)), ValueError, "too many values to unpack (expected 2)")
+raises(swap_sum, 1, TypeError, "cannot unpack non-iterable int object")
+raises(starred, (1,), ValueError, "not enough values to unpack (expected at least 2, got 1)")
+raises(starred, iter([1]), ValueError, "not enough values to unpack (expected at least 2, got 1)")
+raises(unbound, False, UnboundLocalError, "local variable 'z' referenced before assignment")
+raises(delete, 1, UnboundLocalError, "local variable 'y' referenced before assignment")
@@ -0,0 +1,72 @@
+import rupyjit
+
+class Manager:
+    def __init__(self, suppress=False, fail_exit=False):
+        self.log = []
+        self.suppress = suppress
+        self.fail_exit = fail_exit
+
+    def __enter__(self):
+        self.log.append("enter")
+        return 10
+
+    def __exit__(self, exc_type, exc, tb):
+        self.log.append(exc_type)
+        if self.fail_exit:
+            raise KeyError("exit")
+        return self.suppress
+
+def body(cm, x):
+    with cm as v:
+        x = v + x
+    return x
+
+def early_return(cm):
+    with cm:
+        return 1
+
+def raising(cm, x):
+    with cm:
+        x = 1 // x
+    return x
+
+def nested(a, b):
+    with a as x, b as y:
+        return x + y
+
+def no_enter(x):
+    with x:
+        return 1
+
+rupyjit.enable()
+
+m = Manager()
+assert(body(m, 1) == 11)
+assert(m.log == ["enter", None])
+m = Manager()
+assert(early_return(m) == 1)
+assert(m.log == ["enter", None])
+m = Manager()
+try:
+    raising(m, 0)
+    assert(False)
+except ZeroDivisionError:
+    pass
+assert(m.log == ["enter", ZeroDivisionError])
+m = Manager(suppress=True)
+assert(raising(m, 0) == 0)
+assert(m.log == ["enter", ZeroDivisionError])
+m = Manager(fail_exit=True)
+try:
+    raising(m, 0)
+    assert(False)
+except KeyError as e:
+    assert(type(e.__context__) is ZeroDivisionError)
+a, b = Manager(), Manager()
+assert(nested(a, b) == 20)
+assert(a.log == ["enter", None] and b.log == ["enter", None])
+try:
+    no_enter(1)
+    assert(False)
+except AttributeError:
+    pass